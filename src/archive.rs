@@ -0,0 +1,259 @@
+//! Archives that `replace` uploads in one piece and extracts on the remote
+//! server.
+
+use crate::source::SourceFile;
+use crate::util::shell_quote;
+
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+use std::time::SystemTime;
+
+use colored::Colorize;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use openssh::Session;
+use tempfile::NamedTempFile;
+use zip::write::{ ZipWriter, FileOptions };
+
+//------------------------------------------------------------------------------
+/// Format of the archive.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat
+{
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat
+{
+    //--------------------------------------------------------------------------
+    /// Gets the format from its name, `tar.gz` or `zip`.
+    //--------------------------------------------------------------------------
+    pub fn from_name( name: &str ) -> Option<Self>
+    {
+        match name
+        {
+            "tar.gz" | "tgz" => Some(Self::TarGz),
+            "zip" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the file extension of the archive.
+    //--------------------------------------------------------------------------
+    pub fn extension( &self ) -> &'static str
+    {
+        match self
+        {
+            Self::TarGz => "tar.gz",
+            Self::Zip => "zip",
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the program that extracts the archive on the remote server.
+    //--------------------------------------------------------------------------
+    pub fn extractor( &self ) -> &'static str
+    {
+        match self
+        {
+            Self::TarGz => "tar",
+            Self::Zip => "unzip",
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the arguments of the extractor to extract the archive into the
+    /// directory.
+    //--------------------------------------------------------------------------
+    pub fn extract_args( &self, archive: &str, dir: &str ) -> Vec<String>
+    {
+        let args = match self
+        {
+            Self::TarGz => ["-xzf", archive, "-C", dir],
+            Self::Zip => ["-oq", archive, "-d", dir],
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Creates the archive of the files in a temporary file, which is removed when
+/// it is dropped.
+///
+/// # Arguments
+///
+/// - `format` - Format of the archive.
+/// - `files` - Files keyed by the path in the archive.
+//------------------------------------------------------------------------------
+pub fn create_archive
+(
+    format: ArchiveFormat,
+    files: &[(String, &SourceFile)],
+) -> std::io::Result<NamedTempFile>
+{
+    let temp_file = tempfile::Builder::new()
+        .prefix("resm-")
+        .suffix(&(".".to_string() + format.extension()))
+        .tempfile()?;
+    let writer = BufWriter::new(temp_file.reopen()?);
+    match format
+    {
+        ArchiveFormat::TarGz => write_tar_gz(writer, files)?,
+        ArchiveFormat::Zip => write_zip(writer, files)?,
+    }
+    Ok(temp_file)
+}
+
+//------------------------------------------------------------------------------
+/// Gets the staging directory that the archive is extracted into before the
+/// files are moved into the target directory. It is next to the target so
+/// that the target is kept until the extraction succeeds.
+///
+/// # Arguments
+///
+/// - `remote_target_path` - Remote directory that the files are deployed to.
+//------------------------------------------------------------------------------
+pub fn get_staging_path( remote_target_path: &str ) -> String
+{
+    remote_target_path.trim_end_matches('/').to_string() + ".resm-staging"
+}
+
+//------------------------------------------------------------------------------
+/// Gets the remote path that the archive is uploaded to in the staging
+/// directory.
+//------------------------------------------------------------------------------
+pub fn get_remote_archive_path
+(
+    staging_path: &str,
+    format: ArchiveFormat,
+) -> String
+{
+    staging_path.to_string() + "/.resm-archive." + format.extension()
+}
+
+//------------------------------------------------------------------------------
+/// Writes the files as a gzip-compressed tar archive.
+//------------------------------------------------------------------------------
+fn write_tar_gz<W: Write>
+(
+    writer: W,
+    files: &[(String, &SourceFile)],
+) -> std::io::Result<()>
+{
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let encoder = GzEncoder::new(writer, Compression::default());
+    let mut tar = tar::Builder::new(encoder);
+    for (name, file) in files
+    {
+        match file
+        {
+            SourceFile::Path(path) =>
+            {
+                tar.append_file(name, &mut File::open(path)?)?;
+            },
+            SourceFile::Blob(_) =>
+            {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(file.len()?);
+                header.set_mode(file.mode());
+                header.set_mtime(now);
+                header.set_cksum();
+                tar.append_data(&mut header, name, file.open()?)?;
+            },
+        }
+    }
+    tar.into_inner()?.finish()?.flush()
+}
+
+//------------------------------------------------------------------------------
+/// Writes the files as a zip archive.
+//------------------------------------------------------------------------------
+fn write_zip<W: Write + std::io::Seek>
+(
+    writer: W,
+    files: &[(String, &SourceFile)],
+) -> std::io::Result<()>
+{
+    let mut zip = ZipWriter::new(writer);
+    for (name, file) in files
+    {
+        let options = FileOptions::default()
+            .unix_permissions(file.mode())
+            .large_file(file.len()? >= u32::MAX as u64);
+        zip.start_file(name, options)?;
+        std::io::copy(&mut file.open()?, &mut zip)?;
+    }
+    zip.finish()?.flush()
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the extractor of the archive is installed on the remote server.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `format` - Format of the archive.
+//------------------------------------------------------------------------------
+pub async fn check_extractor( session: &Session, format: ArchiveFormat ) -> bool
+{
+    let command = "command -v ".to_string() + &shell_quote(format.extractor());
+    let output = session.shell(&command).output().await.unwrap();
+    if output.status.success() == false
+    {
+        println!
+        (
+            "{} : {} is not installed on the remote server.",
+            "Error".red(),
+            format.extractor(),
+        );
+        return false;
+    }
+    true
+}
+
+//------------------------------------------------------------------------------
+/// Extracts the archive into the remote directory. Returns false if the
+/// extractor failed.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `format` - Format of the archive.
+/// - `archive` - Remote path to the archive.
+/// - `dir` - Remote directory that the archive is extracted into.
+//------------------------------------------------------------------------------
+pub async fn extract_archive
+(
+    session: &Session,
+    format: ArchiveFormat,
+    archive: &str,
+    dir: &str,
+) -> bool
+{
+    println!("{} : {} => {}", "Extracting".green(), archive, dir);
+    let output = session
+        .command(format.extractor())
+        .args(format.extract_args(archive, dir))
+        .output()
+        .await
+        .unwrap();
+    if output.status.success() == false
+    {
+        println!
+        (
+            "{} : Failed to extract {}: {}",
+            "Error".red(),
+            archive,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+        return false;
+    }
+    true
+}
@@ -1,17 +1,22 @@
 //! Specific implementation of operations related to application backup.
 
 use crate::ssh_config::SshConfig;
+use crate::safety::{ print_refused, remove_temp_file };
+use crate::transfer::{ download_file, save_stream };
+use crate::progress::Progress;
 use crate::util::{
     get_session,
     get_sftp_session,
     print_sep,
+    print_dry_run_command,
+    print_dry_run_transfer,
     get_current_time_for_filename,
 };
 
-use std::fs::File;
-use std::io::Write;
+use std::path::Path;
 
 use colored::Colorize;
+use openssh::Stdio;
 
 //------------------------------------------------------------------------------
 /// Backs up the remote directory.
@@ -22,15 +27,22 @@ use colored::Colorize;
 /// - `config` - SSH configuration.
 /// - `target_path` - Relative path from the project directory that you want to
 /// upload.
+/// - `dry_run` - Whether to only print what would be done.
 //------------------------------------------------------------------------------
 pub async fn backup
 (
     project: &str,
     config: &SshConfig,
     target_path: String,
+    dry_run: bool,
 )
 {
     let backup_path = config.backup_path();
+    if backup_path.len() == 0
+    {
+        print_refused(config, "backup_path is empty");
+        return;
+    }
     let mut remote_path = config.remote_path();
     if target_path.len() > 0
     {
@@ -40,13 +52,14 @@ pub async fn backup
     let now = get_current_time_for_filename();
     let backup_file = if target_path.len() > 0
     {
-        format!("{}_{}_{}.zip", project, &target_path, &now)
+        format!("{}_{}_{}.zip", project, target_path.replace('/', "_"), &now)
     }
     else
     {
         format!("{}_{}.zip", project, &now)
     };
     let backup_path = backup_path + "/" + &backup_file;
+    let temp_file = "/tmp/".to_string() + &backup_file;
 
     print_sep();
     println!("{} : {}", "Project    ".green(), &config.project());
@@ -54,30 +67,40 @@ pub async fn backup
     println!("{} : {}", "Backup path".green(), &backup_path);
     println!("{} : {}", "Remote path".green(), &remote_path);
     print_sep();
+
+    if dry_run
+    {
+        println!("{}", "Dry run. No changes will be made.".yellow());
+        print_dry_run_command("zip", &["-r", &temp_file, &remote_path]);
+        print_dry_run_transfer("Download", &temp_file, &backup_path);
+        print_dry_run_command("rm", &["-f", &temp_file]);
+        return;
+    }
+
     println!("Exporting...\n");
 
     //  Gets the backup file.
     let session = get_session(project).await;
     session
         .command("zip")
-        .args(["-r", &backup_file, &remote_path])
+        .args(["-r", &temp_file, &remote_path])
         .output()
         .await
         .unwrap();
     let sftp = get_sftp_session(project).await;
-    {
-        let mut fs = sftp.fs();
-        let content = fs.read(&backup_file).await.unwrap();
-        let mut file = File::create(&backup_path).unwrap();
-        file.write_all(&content).unwrap();
-    }
+    let size = sftp.fs().metadata(&temp_file).await.unwrap().len();
+    let progress = Progress::new("Downloading", 1, size.unwrap_or(0));
+    download_file
+    (
+        &sftp.options(),
+        &temp_file,
+        Path::new(&backup_path),
+        &progress,
+    ).await.unwrap();
+    progress.inc_file();
+    progress.finish();
     sftp.close().await.unwrap();
-    session
-        .command("rm")
-        .arg(&backup_file)
-        .output()
-        .await
-        .unwrap();
+    remove_temp_file(&session, config, &temp_file).await;
     session.close().await.unwrap();
     println!("Done.");
 }
@@ -90,14 +113,22 @@ pub async fn backup
 /// - `project` - Project name.
 /// - `config` - SSH configuration.
 /// - `target_tables` - Tables to be backed up.
+/// - `dry_run` - Whether to only print what would be done.
 //------------------------------------------------------------------------------
 pub async fn backup_db
 (
     project: &str,
     config: &SshConfig,
     target_tables: Vec<String>,
+    dry_run: bool,
 )
 {
+    if config.backup_path().len() == 0
+    {
+        print_refused(config, "backup_path is empty");
+        return;
+    }
+
     let now = get_current_time_for_filename();
     let backup_file = format!("{}_{}.sql", project, &now);
     let backup_path = config.backup_path() + "/" + &backup_file;
@@ -112,6 +143,26 @@ pub async fn backup_db
         println!("{} : {}", "Target tables".green(), &target_tables.join(", "));
     }
     print_sep();
+
+    if dry_run
+    {
+        //  The password is masked so that it is not printed.
+        println!("{}", "Dry run. No changes will be made.".yellow());
+        let mut args = vec!
+        [
+            "-h".to_string(), config.db_host_reader(),
+            "-P".to_string(), config.db_port().to_string(),
+            "-u".to_string(), config.db_root_user(),
+            "-p********".to_string(),
+            config.db_name(),
+        ];
+        args.extend(target_tables);
+        let args = args.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
+        print_dry_run_command("mysqldump", &args);
+        print_dry_run_transfer("Save", "mysqldump output", &backup_path);
+        return;
+    }
+
     println!("Exporting...\n");
 
     //  Gets the backup file.
@@ -121,7 +172,7 @@ pub async fn backup_db
         .iter()
         .map(|x| x.as_str())
         .collect::<Vec<&str>>();
-    let dump = session
+    let mut dump = session
         .command("mysqldump")
         .args([
             "-h", &config.db_host_reader(),
@@ -131,12 +182,26 @@ pub async fn backup_db
             &config.db_name(),
         ])
         .args(target_tables)
-        .output()
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .await
+        .unwrap();
+
+    //  The dump is written to the file as it is received.
+    let mut stdout = dump.stdout().take().unwrap();
+    let progress = Progress::new("Dumping", 0, 0);
+    save_stream(&mut stdout, Path::new(&backup_path), &progress)
         .await
         .unwrap();
-    println!("{}", String::from_utf8_lossy(&dump.stderr));
-    let mut file = File::create(&backup_path).unwrap();
-    file.write_all(&dump.stdout).unwrap();
+    progress.finish();
+    drop(stdout);
+    let status = dump.wait().await.unwrap();
     session.close().await.unwrap();
+    if status.success() == false
+    {
+        println!("{} : mysqldump exited with {}", "Error".red(), status);
+        return;
+    }
     println!("Done.");
 }
@@ -0,0 +1,394 @@
+//! Files changed in the local repository to be reflected on the remote server.
+
+use crate::ssh_config::SshConfig;
+use crate::source::{ SourceFile, GitBlob };
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use colored::Colorize;
+use git2::{
+    Delta,
+    DiffFindOptions,
+    DiffOptions,
+    FileMode,
+    Repository,
+    Status,
+    StatusOptions,
+};
+
+//------------------------------------------------------------------------------
+/// Changed files classified by the kind of change.
+///
+/// All paths are relative to the source directory.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct ChangeSet
+{
+    pub added: BTreeMap<String, SourceFile>,
+    pub modified: BTreeMap<String, SourceFile>,
+
+    /// Map from new paths to old paths and the new files.
+    pub renamed: BTreeMap<String, (String, SourceFile)>,
+    pub deleted: Vec<String>,
+}
+
+impl ChangeSet
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether there are no changes.
+    //--------------------------------------------------------------------------
+    pub fn is_empty( &self ) -> bool
+    {
+        self.added.len() == 0
+            && self.modified.len() == 0
+            && self.renamed.len() == 0
+            && self.deleted.len() == 0
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the files to be uploaded.
+    //--------------------------------------------------------------------------
+    pub fn uploads( &self ) -> Vec<(&String, &SourceFile)>
+    {
+        let mut uploads: Vec<(&String, &SourceFile)> = self.added
+            .iter()
+            .chain(self.modified.iter())
+            .collect();
+        for (path, (_, file)) in &self.renamed
+        {
+            uploads.push((path, file));
+        }
+        uploads
+    }
+
+    //--------------------------------------------------------------------------
+    /// Prints the changed files separately for each kind of change.
+    //--------------------------------------------------------------------------
+    pub fn print( &self )
+    {
+        if self.added.len() > 0
+        {
+            println!("{} :", "Added      ".green());
+            for (path, file) in &self.added
+            {
+                println!("    + {}", file.display(path));
+            }
+        }
+        if self.modified.len() > 0
+        {
+            println!("{} :", "Modified   ".green());
+            for (path, file) in &self.modified
+            {
+                println!("    ~ {}", file.display(path));
+            }
+        }
+        if self.renamed.len() > 0
+        {
+            println!("{} :", "Renamed    ".green());
+            for (path, (old_path, _)) in &self.renamed
+            {
+                println!("    > {} => {}", old_path, path);
+            }
+        }
+        if self.deleted.len() > 0
+        {
+            println!("{} :", "Deleted    ".green());
+            for path in &self.deleted
+            {
+                println!("    - {}", path);
+            }
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the files described in the patch file. All files are treated as
+/// modified.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+/// - `patch_file` - Path to the file that describes the files to be uploaded.
+//------------------------------------------------------------------------------
+pub fn get_patch_file_changes
+(
+    config: &SshConfig,
+    patch_file: &str,
+) -> ChangeSet
+{
+    let git_src_path = config.git_src_path();
+    let mut changes = ChangeSet::default();
+
+    let patch_file = std::fs::read_to_string(patch_file).unwrap();
+    for line in patch_file.lines()
+    {
+        let line = line.trim();
+        if line.len() == 0
+        {
+            continue;
+        }
+        let path = PathBuf::from(git_src_path.clone() + "/" + line);
+        changes.modified.insert(line.to_string(), SourceFile::Path(path));
+    }
+    changes
+}
+
+//------------------------------------------------------------------------------
+/// Gets the uncommitted changes in the working tree and the current branch
+/// name.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub fn get_working_tree_changes
+(
+    config: &SshConfig,
+) -> Result<(Option<String>, ChangeSet), git2::Error>
+{
+    let git_path = config.git_path();
+    let git_relative_path = config.get_git_relative_path();
+    let repo = Repository::open(&git_path)?;
+    let mut status_opts = StatusOptions::new();
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .renames_head_to_index(true)
+        .renames_index_to_workdir(true);
+    let statuses = repo.statuses(Some(&mut status_opts))?;
+
+    let mut changes = ChangeSet::default();
+    for entry in statuses.iter()
+    {
+        let status = entry.status();
+        if status.intersects(Status::IGNORED | Status::CONFLICTED)
+        {
+            continue;
+        }
+
+        let path = entry.path().unwrap().to_string();
+        let relative_path = get_relative_path(&git_relative_path, &path);
+        let source_file = SourceFile::Path(PathBuf::from
+        (
+            git_path.clone() + "/" + &path,
+        ));
+
+        if status.contains(Status::WT_DELETED)
+            || (status.contains(Status::INDEX_DELETED)
+                && status.contains(Status::WT_NEW) == false)
+        {
+            if let Some(relative_path) = relative_path
+            {
+                changes.deleted.push(relative_path);
+            }
+            continue;
+        }
+
+        if status.intersects(Status::INDEX_RENAMED | Status::WT_RENAMED)
+        {
+            //  Gets the paths before and after the rename.
+            let get_path = |file: git2::DiffFile| -> Option<String>
+            {
+                file.path().map(|path| path.to_str().unwrap().to_string())
+            };
+            let head_to_index = entry.head_to_index();
+            let index_to_workdir = entry.index_to_workdir();
+            let mut old_path = None;
+            let mut new_path = path;
+            if let Some(diff) = &head_to_index
+            {
+                if status.contains(Status::INDEX_RENAMED)
+                {
+                    old_path = get_path(diff.old_file());
+                    new_path = get_path(diff.new_file()).unwrap_or(new_path);
+                }
+            }
+            if let Some(diff) = &index_to_workdir
+            {
+                if status.contains(Status::WT_RENAMED)
+                {
+                    old_path = old_path.or(get_path(diff.old_file()));
+                    new_path = get_path(diff.new_file()).unwrap_or(new_path);
+                }
+            }
+
+            let source_file = SourceFile::Path(PathBuf::from
+            (
+                git_path.clone() + "/" + &new_path,
+            ));
+            add_rename
+            (
+                &mut changes,
+                old_path.and_then(|path|
+                {
+                    get_relative_path(&git_relative_path, &path)
+                }),
+                get_relative_path(&git_relative_path, &new_path),
+                source_file,
+            );
+            continue;
+        }
+
+        let relative_path = match relative_path
+        {
+            Some(relative_path) => relative_path,
+            None => continue,
+        };
+        if status.intersects(Status::WT_NEW | Status::INDEX_NEW)
+        {
+            changes.added.insert(relative_path, source_file);
+        }
+        else
+        {
+            changes.modified.insert(relative_path, source_file);
+        }
+    }
+
+    let head = repo.head()?;
+    let branch_name = head.shorthand().map(|name| name.to_string());
+    Ok((branch_name, changes))
+}
+
+//------------------------------------------------------------------------------
+/// Gets the changes between the specified git revisions. The content of the
+/// files is read from the `to` revision.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+/// - `from` - Revision to compute the changes from.
+/// - `to` - Revision to compute the changes to.
+//------------------------------------------------------------------------------
+pub fn get_revision_range_changes
+(
+    config: &SshConfig,
+    from: &str,
+    to: &str,
+) -> Result<(String, String, ChangeSet), git2::Error>
+{
+    let repo = Repository::open(config.git_path())?;
+    let from_commit = repo.revparse_single(from)?.peel_to_commit()?;
+    let to_commit = repo.revparse_single(to)?.peel_to_commit()?;
+
+    let git_relative_path = config.get_git_relative_path();
+    let mut diff = repo.diff_tree_to_tree
+    (
+        Some(&from_commit.tree()?),
+        Some(&to_commit.tree()?),
+        Some(&mut DiffOptions::new()),
+    )?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+    let mut changes = ChangeSet::default();
+    for delta in diff.deltas()
+    {
+        let old_file = delta.old_file();
+        let new_file = delta.new_file();
+        let old_relative_path = old_file
+            .path()
+            .and_then(|path| path.to_str())
+            .and_then(|path| get_relative_path(&git_relative_path, path));
+        let relative_path = new_file
+            .path()
+            .and_then(|path| path.to_str())
+            .and_then(|path| get_relative_path(&git_relative_path, path));
+
+        if delta.status() == Delta::Deleted
+        {
+            if let Some(old_relative_path) = old_relative_path
+            {
+                changes.deleted.push(old_relative_path);
+            }
+            continue;
+        }
+
+        //  Skips submodules and symbolic links.
+        if new_file.mode() != FileMode::Blob
+            && new_file.mode() != FileMode::BlobExecutable
+        {
+            continue;
+        }
+        let executable = new_file.mode() == FileMode::BlobExecutable;
+        let blob = GitBlob::new(&repo, new_file.id(), executable)?;
+        let source_file = SourceFile::Blob(blob);
+
+        match delta.status()
+        {
+            Delta::Renamed =>
+            {
+                add_rename
+                (
+                    &mut changes,
+                    old_relative_path,
+                    relative_path,
+                    source_file,
+                );
+            },
+            Delta::Added | Delta::Copied =>
+            {
+                if let Some(relative_path) = relative_path
+                {
+                    changes.added.insert(relative_path, source_file);
+                }
+            },
+            Delta::Modified | Delta::Typechange =>
+            {
+                if let Some(relative_path) = relative_path
+                {
+                    changes.modified.insert(relative_path, source_file);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok((from_commit.id().to_string(), to_commit.id().to_string(), changes))
+}
+
+//------------------------------------------------------------------------------
+/// Adds the renamed file. If either side of the rename is outside the source
+/// directory, it is treated as an addition or a deletion.
+//------------------------------------------------------------------------------
+fn add_rename
+(
+    changes: &mut ChangeSet,
+    old_relative_path: Option<String>,
+    relative_path: Option<String>,
+    source_file: SourceFile,
+)
+{
+    match (old_relative_path, relative_path)
+    {
+        (Some(old_relative_path), Some(relative_path)) =>
+        {
+            changes.renamed
+                .insert(relative_path, (old_relative_path, source_file));
+        },
+        (None, Some(relative_path)) =>
+        {
+            changes.added.insert(relative_path, source_file);
+        },
+        (Some(old_relative_path), None) =>
+        {
+            changes.deleted.push(old_relative_path);
+        },
+        (None, None) => {},
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the path relative to the source directory from the path relative to
+/// the git repository. Returns `None` if the path is outside the source
+/// directory.
+//------------------------------------------------------------------------------
+fn get_relative_path( git_relative_path: &str, path: &str ) -> Option<String>
+{
+    if git_relative_path.len() == 0
+    {
+        return Some(path.to_string());
+    }
+    path
+        .strip_prefix(&(git_relative_path.to_string() + "/"))
+        .map(|path| path.to_string())
+}
@@ -0,0 +1,253 @@
+//! Formats of the configuration files.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+//------------------------------------------------------------------------------
+/// Extensions of the configuration files that are loaded.
+//------------------------------------------------------------------------------
+pub const CONFIG_EXTENSIONS: [&str; 4] = ["json", "toml", "yaml", "yml"];
+
+//------------------------------------------------------------------------------
+/// TOML configuration file, whose entries are written as `[[entries]]` tables
+/// since a TOML document cannot be an array.
+//------------------------------------------------------------------------------
+#[derive(Debug, Deserialize)]
+struct TomlFile
+{
+    #[serde(default)]
+    entries: Vec<Value>,
+}
+
+//------------------------------------------------------------------------------
+/// Error of parsing a configuration file.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct ParseError
+{
+    /// Line of the error, starting from 1.
+    pub line: Option<usize>,
+
+    /// Description of the error.
+    pub message: String,
+}
+
+//------------------------------------------------------------------------------
+/// Gets the extension of the configuration file.
+//------------------------------------------------------------------------------
+fn get_extension( path: &Path ) -> &str
+{
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+}
+
+//------------------------------------------------------------------------------
+/// Parses the content of the configuration file into the entries as JSON
+/// values, according to the extension of the file.
+///
+/// # Arguments
+///
+/// - `path` - Path to the configuration file.
+/// - `content` - Content of the configuration file.
+//------------------------------------------------------------------------------
+pub fn parse_entries
+(
+    path: &Path,
+    content: &str,
+) -> Result<Vec<Value>, ParseError>
+{
+    match get_extension(path)
+    {
+        "toml" =>
+        {
+            toml::from_str::<TomlFile>(content)
+                .map(|file| file.entries)
+                .map_err(|e| ParseError
+                {
+                    line: e.span().map(|span|
+                    {
+                        content[..span.start].matches('\n').count() + 1
+                    }),
+                    message: e.message().to_string(),
+                })
+        },
+        "yaml" | "yml" =>
+        {
+            serde_yaml::from_str::<Vec<Value>>(content)
+                .map_err(|e| ParseError
+                {
+                    line: e.location().map(|location| location.line()),
+                    message: e.to_string(),
+                })
+        },
+        _ =>
+        {
+            serde_json::from_str::<Vec<Value>>(content)
+                .map_err(|e|
+                {
+                    //  Drops the position, which is reported as the line.
+                    let message = e.to_string();
+                    let message = match message.rfind(" at line ")
+                    {
+                        Some(pos) => message[..pos].to_string(),
+                        None => message,
+                    };
+                    ParseError { line: Some(e.line()), message }
+                })
+        },
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Finds the line of each entry in the configuration file, starting from 1.
+///
+/// # Arguments
+///
+/// - `path` - Path to the configuration file.
+/// - `content` - Content of the configuration file.
+//------------------------------------------------------------------------------
+pub fn find_entry_lines( path: &Path, content: &str ) -> Vec<usize>
+{
+    match get_extension(path)
+    {
+        "toml" =>
+        {
+            find_lines(content, |line| line.trim() == "[[entries]]")
+        },
+        "yaml" | "yml" =>
+        {
+            find_lines(content, |line| line == "-" || line.starts_with("- "))
+        },
+        _ => find_json_entry_lines(content),
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Finds the lines that match the predicate.
+//------------------------------------------------------------------------------
+fn find_lines<F>( content: &str, predicate: F ) -> Vec<usize>
+where
+    F: Fn(&str) -> bool,
+{
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| predicate(line))
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+//------------------------------------------------------------------------------
+/// Finds the lines of the objects in the top-level JSON array.
+//------------------------------------------------------------------------------
+fn find_json_entry_lines( content: &str ) -> Vec<usize>
+{
+    let mut lines = Vec::new();
+    let mut line = 1;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in content.chars()
+    {
+        if c == '\n'
+        {
+            line += 1;
+        }
+        if in_string
+        {
+            match c
+            {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+        match c
+        {
+            '"' => in_string = true,
+            '[' | '{' =>
+            {
+                if c == '{' && depth == 1
+                {
+                    lines.push(line);
+                }
+                depth += 1;
+            },
+            ']' | '}' => depth -= 1,
+            _ => {},
+        }
+    }
+    lines
+}
+
+//------------------------------------------------------------------------------
+/// Finds the line of the field in the entry of the configuration file, by
+/// looking for the keys of the field path one after another. Returns None if
+/// the field is not found.
+///
+/// # Arguments
+///
+/// - `path` - Path to the configuration file.
+/// - `content` - Content of the configuration file.
+/// - `index` - Position of the entry in the file.
+/// - `field` - Field path, such as `connect_info.host` or `tunnels[0].host`.
+//------------------------------------------------------------------------------
+pub fn find_field_line
+(
+    path: &Path,
+    content: &str,
+    index: usize,
+    field: &str,
+) -> Option<usize>
+{
+    let entry_lines = find_entry_lines(path, content);
+    let start = *entry_lines.get(index)?;
+    let end = entry_lines.get(index + 1).copied().unwrap_or(usize::MAX);
+    let extension = get_extension(path);
+
+    let lines: Vec<&str> = content.lines().collect();
+    let mut line = start;
+    for key in field.split(['.', '[']).filter(|key| key.ends_with(']') == false)
+    {
+        line = (line..end.min(lines.len() + 1)).find(|&i|
+        {
+            is_key_line(extension, lines[i - 1], key)
+        })?;
+    }
+    Some(line)
+}
+
+//------------------------------------------------------------------------------
+/// Checks whether the key is written in the line.
+//------------------------------------------------------------------------------
+fn is_key_line( extension: &str, line: &str, key: &str ) -> bool
+{
+    let line = line.trim().trim_start_matches("- ");
+    match extension
+    {
+        "toml" =>
+        {
+            let header = ".".to_string() + key;
+            line.split(['=', '{', ',', ' ']).any(|token| token == key)
+                || (line.starts_with('[') && line.contains(&header))
+        },
+        "yaml" | "yml" =>
+        {
+            line.starts_with(&(key.to_string() + ":"))
+                || line.contains(&(" ".to_string() + key + ":"))
+        },
+        _ =>
+        {
+            let quoted = "\"".to_string() + key + "\"";
+            line.match_indices(&quoted).any(|(pos, _)|
+            {
+                line[pos + quoted.len()..].trim_start().starts_with(':')
+            })
+        },
+    }
+}
@@ -1,17 +1,32 @@
 //! Information required for SSH connection.
 
+use crate::schema::secret_schema;
+
+use schemars::JsonSchema;
 use serde::{ Deserialize, Serialize };
 
 //------------------------------------------------------------------------------
 /// A structure that summarizes information necessary for SSH connection.
 //------------------------------------------------------------------------------
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct ConnectInfo
 {
+    /// Host name or IP address to connect to.
     host: String,
+
+    /// Port number of the server.
     port: Option<u16>,
+
+    /// User name.
     user: Option<String>,
+
+    /// Password (entering the password cannot be omitted).
+    #[serde(default)]
+    #[schemars(schema_with = "secret_schema")]
     password: Option<String>,
+
+    /// Path to the identity file (absolute path).
     identity_file: Option<String>,
 }
 
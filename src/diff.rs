@@ -0,0 +1,273 @@
+//! Specific implementation of operations related to comparing the local source
+//! tree with the remote directory.
+
+use crate::ssh_config::SshConfig;
+use crate::source::get_working_tree_files;
+use crate::sync::get_remote_entries;
+use crate::transfer::{ get_reader_hash, get_remote_file_hash };
+use crate::manifest::MANIFEST_FILE;
+use crate::util::{ get_sftp_session, print_sep };
+
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+use similar::TextDiff;
+
+//------------------------------------------------------------------------------
+/// Compares the local source tree with the remote directory.
+///
+/// # Arguments
+///
+/// - `project` - Project name.
+/// - `config` - SSH configuration.
+/// - `target_path` - Relative path from the project directory to compare.
+/// - `unified` - Whether to print unified diffs for text files.
+//------------------------------------------------------------------------------
+pub async fn diff
+(
+    project: &str,
+    config: &SshConfig,
+    target_path: &str,
+    unified: bool,
+)
+{
+    let remote_path = config.remote_path();
+    let mut remote_target_path = remote_path.clone();
+    let mut local_target_path = config.source_path();
+    if target_path.len() > 0
+    {
+        remote_target_path = remote_target_path + "/" + target_path;
+        local_target_path = local_target_path + "/" + target_path;
+    }
+
+    print_sep();
+    println!("{} : {}", "Project    ".green(), &config.project());
+    println!("{} : {}", "Environment".green(), &config.environment());
+    println!("{} : {}", "Local path ".green(), &local_target_path);
+    println!("{} : {}", "Remote path".green(), &remote_target_path);
+    print_sep();
+
+    let local_files = match get_working_tree_files(config, target_path)
+    {
+        Ok(files) => files,
+        Err(e) =>
+        {
+            println!("{} : {}", "Error".red(), e);
+            return;
+        },
+    };
+
+    let sftp = get_sftp_session(project).await;
+    let mut fs = sftp.fs();
+    let options = sftp.options();
+    let mut remote_entries = BTreeMap::new();
+    get_remote_entries
+    (
+        &mut fs,
+        &remote_path,
+        &remote_target_path,
+        &mut remote_entries,
+    ).await;
+    let remote_files: BTreeMap<String, u64> = remote_entries
+        .into_iter()
+        .filter(|(path, metadata)|
+        {
+            path != MANIFEST_FILE
+                && metadata.file_type().map(|t| t.is_file()).unwrap_or(false)
+        })
+        .map(|(path, metadata)| (path, metadata.len().unwrap_or(0)))
+        .collect();
+
+    //  Compares the files that exist on both sides by size, and then by hash
+    //  read chunk by chunk when the sizes are the same.
+    let mut only_local = Vec::new();
+    let mut different = Vec::new();
+    let mut unreadable = Vec::new();
+    for (relative_path, local_file) in &local_files
+    {
+        let remote_size = match remote_files.get(relative_path)
+        {
+            Some(size) => *size,
+            None =>
+            {
+                only_local.push(relative_path.clone());
+                continue;
+            },
+        };
+        let local_size = match local_file.len()
+        {
+            Ok(size) => size,
+            Err(e) =>
+            {
+                let path = local_file.display(relative_path);
+                unreadable.push((path, e.to_string()));
+                continue;
+            },
+        };
+        if remote_size != local_size
+        {
+            different.push(relative_path.clone());
+            continue;
+        }
+
+        let local_hash = match local_file
+            .open()
+            .and_then(|mut reader| get_reader_hash(&mut reader))
+        {
+            Ok(hash) => hash,
+            Err(e) =>
+            {
+                let path = local_file.display(relative_path);
+                unreadable.push((path, e.to_string()));
+                continue;
+            },
+        };
+        let remote_file_path = remote_path.clone() + "/" + relative_path;
+        match get_remote_file_hash(&options, &remote_file_path).await
+        {
+            Ok(remote_hash) if remote_hash == local_hash => {},
+            Ok(_) => different.push(relative_path.clone()),
+            Err(e) => unreadable.push((remote_file_path, e.to_string())),
+        }
+    }
+    let only_remote: Vec<&String> = remote_files
+        .keys()
+        .filter(|path| local_files.contains_key(*path) == false)
+        .collect();
+
+    println!
+    (
+        "{} : {} only local, {} only remote, {} different, {} unreadable",
+        "Summary    ".green(),
+        only_local.len(),
+        only_remote.len(),
+        different.len(),
+        unreadable.len(),
+    );
+    for path in &only_local
+    {
+        println!("    {} {}", "<".green(), path);
+    }
+    for path in &only_remote
+    {
+        println!("    {} {}", ">".red(), path);
+    }
+    for path in &different
+    {
+        println!("    {} {}", "~".yellow(), path);
+    }
+    for (path, e) in &unreadable
+    {
+        println!("    {} {}: {}", "!".red(), path, e);
+    }
+
+    //  Prints unified diffs from the remote files to the local files. The
+    //  contents are loaded one file at a time.
+    if unified
+    {
+        for path in &different
+        {
+            print_sep();
+            let local_file = &local_files[path];
+            let local_content = match local_file.read()
+            {
+                Ok(content) => content,
+                Err(e) =>
+                {
+                    println!
+                    (
+                        "{} : Failed to read {}: {}",
+                        "Error".red(),
+                        local_file.display(path),
+                        e,
+                    );
+                    continue;
+                },
+            };
+            let remote_file_path = remote_path.clone() + "/" + path;
+            let remote_content = match fs.read(&remote_file_path).await
+            {
+                Ok(content) => content,
+                Err(e) =>
+                {
+                    println!
+                    (
+                        "{} : Failed to read {}: {}",
+                        "Error".red(),
+                        remote_file_path,
+                        e,
+                    );
+                    continue;
+                },
+            };
+            print_unified_diff(path, &local_content, &remote_content);
+        }
+    }
+    drop(fs);
+    sftp.close().await.unwrap();
+    print_sep();
+}
+
+//------------------------------------------------------------------------------
+/// Prints the unified diff from the remote file to the local file, or a note
+/// if either of them is binary.
+//------------------------------------------------------------------------------
+fn print_unified_diff( path: &str, local_content: &[u8], remote_content: &[u8] )
+{
+    let (local_text, remote_text) = match
+    (
+        get_text(local_content),
+        get_text(remote_content),
+    )
+    {
+        (Some(local_text), Some(remote_text)) => (local_text, remote_text),
+        _ =>
+        {
+            println!("Binary files differ: {}", path);
+            return;
+        },
+    };
+
+    let remote_name = "remote/".to_string() + path;
+    let local_name = "local/".to_string() + path;
+    let text_diff = TextDiff::from_lines(remote_text, local_text);
+    let unified_diff = text_diff
+        .unified_diff()
+        .header(&remote_name, &local_name)
+        .to_string();
+    for line in unified_diff.lines()
+    {
+        if line.starts_with("+++") || line.starts_with("---")
+        {
+            println!("{}", line.bold());
+        }
+        else if line.starts_with('+')
+        {
+            println!("{}", line.green());
+        }
+        else if line.starts_with('-')
+        {
+            println!("{}", line.red());
+        }
+        else if line.starts_with("@@")
+        {
+            println!("{}", line.cyan());
+        }
+        else
+        {
+            println!("{}", line);
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Returns the content as text if it is not binary.
+//------------------------------------------------------------------------------
+fn get_text( content: &[u8] ) -> Option<&str>
+{
+    if content.contains(&0)
+    {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
@@ -0,0 +1,167 @@
+//! Commands executed before and after a deploy.
+
+use crate::util::{ print_sep, shell_quote };
+
+use std::process::Command;
+
+use colored::Colorize;
+use openssh::{ Session, Stdio };
+use schemars::JsonSchema;
+use serde::{ Deserialize, Serialize };
+
+//------------------------------------------------------------------------------
+/// Commands executed on the local machine and on the remote server.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Hooks
+{
+    /// Commands run in `git_path` on the local machine.
+    local: Option<Vec<String>>,
+
+    /// Commands run in `remote_path` on the destination server.
+    remote: Option<Vec<String>>,
+}
+
+impl Hooks
+{
+    //--------------------------------------------------------------------------
+    /// Returns the commands executed on the local machine.
+    //--------------------------------------------------------------------------
+    pub fn local( &self ) -> Vec<String>
+    {
+        self.local.clone().unwrap_or_default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the commands executed on the remote server.
+    //--------------------------------------------------------------------------
+    pub fn remote( &self ) -> Vec<String>
+    {
+        self.remote.clone().unwrap_or_default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether there are no commands.
+    //--------------------------------------------------------------------------
+    pub fn is_empty( &self ) -> bool
+    {
+        self.local().len() == 0 && self.remote().len() == 0
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Runs the hook commands, the local ones first, streaming their output.
+/// Stops at the first command that fails and returns false.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `name` - Name of the hook, such as `pre_deploy`.
+/// - `hooks` - Commands to run.
+/// - `local_dir` - Working directory of the local commands.
+/// - `remote_dir` - Working directory of the remote commands.
+//------------------------------------------------------------------------------
+pub async fn run_hooks
+(
+    session: &Session,
+    name: &str,
+    hooks: &Hooks,
+    local_dir: &str,
+    remote_dir: &str,
+) -> bool
+{
+    if hooks.is_empty()
+    {
+        return true;
+    }
+
+    print_sep();
+    println!("{} : {}", "Hook".green(), name);
+    print_sep();
+    for command in hooks.local()
+    {
+        println!("{} : {}", "Local ".green(), &command);
+        if run_local_command(&command, local_dir) == false
+        {
+            return false;
+        }
+    }
+
+    for command in hooks.remote()
+    {
+        println!("{} : {}", "Remote".green(), &command);
+        let status = session
+            .shell(format!("cd {} && {}", shell_quote(remote_dir), &command))
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .await;
+        match status
+        {
+            Ok(status) if status.success() => {},
+            Ok(status) =>
+            {
+                println!("{} : {} ({})", "Failed".red(), &command, status);
+                return false;
+            },
+            Err(e) =>
+            {
+                println!("{} : {} ({})", "Failed".red(), &command, e);
+                return false;
+            },
+        }
+    }
+    true
+}
+
+//------------------------------------------------------------------------------
+/// Runs the command on the local machine, streaming its output. Returns false
+/// if the command fails.
+///
+/// # Arguments
+///
+/// - `command` - Command to run with `sh -c`.
+/// - `dir` - Working directory of the command.
+//------------------------------------------------------------------------------
+pub fn run_local_command( command: &str, dir: &str ) -> bool
+{
+    let status = Command::new("sh")
+        .args(["-c", command])
+        .current_dir(dir)
+        .status();
+    match status
+    {
+        Ok(status) if status.success() => true,
+        Ok(status) =>
+        {
+            println!("{} : {} ({})", "Failed".red(), command, status);
+            false
+        },
+        Err(e) =>
+        {
+            println!("{} : {} ({})", "Failed".red(), command, e);
+            false
+        },
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Prints what `run_hooks` would do in dry run mode.
+//------------------------------------------------------------------------------
+pub fn print_hooks_plan
+(
+    hooks: &Hooks,
+    local_dir: &str,
+    remote_dir: &str,
+)
+{
+    for command in hooks.local()
+    {
+        println!("{} : (in {}) {}", "Local  ".yellow(), local_dir, &command);
+    }
+    for command in hooks.remote()
+    {
+        println!("{} : (in {}) {}", "Remote ".yellow(), remote_dir, &command);
+    }
+}
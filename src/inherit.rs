@@ -0,0 +1,334 @@
+//! Inheritance between configuration entries with `extends`.
+
+use crate::interpolate::is_secret_reference;
+
+use std::collections::{ BTreeMap, HashMap };
+use std::fmt;
+
+use colored::Colorize;
+use serde_json::{ Map, Value };
+
+//------------------------------------------------------------------------------
+/// Configuration entry as written in a file.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct RawEntry
+{
+    /// Entry as a JSON object.
+    pub value: Value,
+
+    /// Path to the file that the entry was loaded from.
+    pub file: String,
+
+    /// Position of the entry in the file.
+    pub index: usize,
+}
+
+//------------------------------------------------------------------------------
+/// Entry and file that a value comes from.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Origin
+{
+    /// Key of the entry.
+    pub entry: String,
+
+    /// Path to the file that the entry was loaded from.
+    pub file: String,
+
+    /// Position of the entry in the file.
+    pub index: usize,
+}
+
+impl Origin
+{
+    //--------------------------------------------------------------------------
+    /// Gets the origin of the values written in the entry.
+    //--------------------------------------------------------------------------
+    pub fn of( entry: &RawEntry ) -> Self
+    {
+        Self
+        {
+            entry: get_entry_key(&entry.value),
+            file: entry.file.clone(),
+            index: entry.index,
+        }
+    }
+}
+
+impl fmt::Display for Origin
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "{} ({})", &self.entry, &self.file)
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Configuration entry with the inherited values merged in.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct ResolvedEntry
+{
+    /// Entry as a JSON object.
+    pub value: Value,
+
+    /// Origin of each value keyed by its field path, such as
+    /// `connect_info.host`.
+    pub origins: BTreeMap<String, Origin>,
+}
+
+//------------------------------------------------------------------------------
+/// Gets the key of the entry, which is the project name followed by the
+/// environment name.
+///
+/// # Arguments
+///
+/// - `value` - Entry as a JSON object.
+//------------------------------------------------------------------------------
+pub fn get_entry_key( value: &Value ) -> String
+{
+    let project = value["project"].as_str().unwrap_or("");
+    let environment = value["environment"].as_str().unwrap_or("");
+    if environment.len() > 0
+    {
+        return project.to_string() + "_" + environment;
+    }
+    project.to_string()
+}
+
+//------------------------------------------------------------------------------
+/// Resolves the `extends` field of the entries. An entry that extends another
+/// one starts from the resolved values of that entry and overrides them with
+/// its own values: objects are merged field by field, and any other value,
+/// including arrays, replaces the inherited one. Returns the result for each
+/// entry in the same order.
+///
+/// # Arguments
+///
+/// - `entries` - Entries as written in the files.
+//------------------------------------------------------------------------------
+pub fn resolve_entries
+(
+    entries: &[RawEntry],
+) -> Vec<Result<ResolvedEntry, String>>
+{
+    let mut indices = HashMap::new();
+    for (i, entry) in entries.iter().enumerate()
+    {
+        indices.insert(get_entry_key(&entry.value), i);
+    }
+
+    let mut cache = HashMap::new();
+    (0..entries.len())
+        .map(|i| resolve(entries, &indices, i, &mut Vec::new(), &mut cache))
+        .collect()
+}
+
+//------------------------------------------------------------------------------
+/// Resolves the entry at the index, following its `extends` chain.
+//------------------------------------------------------------------------------
+fn resolve
+(
+    entries: &[RawEntry],
+    indices: &HashMap<String, usize>,
+    index: usize,
+    chain: &mut Vec<usize>,
+    cache: &mut HashMap<usize, ResolvedEntry>,
+) -> Result<ResolvedEntry, String>
+{
+    if let Some(resolved) = cache.get(&index)
+    {
+        return Ok(resolved.clone());
+    }
+
+    let entry = &entries[index];
+    if entry.value.is_object() == false
+    {
+        return Err("entry is not an object".to_string());
+    }
+    let origin = Origin::of(entry);
+
+    let mut resolved = match &entry.value["extends"]
+    {
+        Value::Null => ResolvedEntry
+        {
+            value: Value::Object(Map::new()),
+            origins: BTreeMap::new(),
+        },
+        Value::String(base) =>
+        {
+            let base_index = match indices.get(base)
+            {
+                Some(base_index) => *base_index,
+                None => return Err(format!("extends unknown entry {}", base)),
+            };
+            if base_index == index || chain.contains(&base_index)
+            {
+                return Err(format!("extends forms a cycle through {}", base));
+            }
+
+            chain.push(index);
+            let base = resolve(entries, indices, base_index, chain, cache);
+            chain.pop();
+            let mut base = base?;
+
+            //  The base of the base is not inherited.
+            base.value.as_object_mut().unwrap().remove("extends");
+            base.origins.remove("extends");
+            base
+        },
+        _ => return Err("extends must be a string".to_string()),
+    };
+
+    merge
+    (
+        &mut resolved.value,
+        &mut resolved.origins,
+        &entry.value,
+        &origin,
+        "",
+    );
+    cache.insert(index, resolved.clone());
+    Ok(resolved)
+}
+
+//------------------------------------------------------------------------------
+/// Merges the values of the child object into the base object recursively and
+/// records the origin of the merged values.
+///
+/// # Arguments
+///
+/// - `base` - Base object to merge into.
+/// - `origins` - Origins of the values in the base object.
+/// - `child` - Child object whose values take precedence.
+/// - `origin` - Origin of the values in the child object.
+/// - `path` - Field path of the objects.
+//------------------------------------------------------------------------------
+fn merge
+(
+    base: &mut Value,
+    origins: &mut BTreeMap<String, Origin>,
+    child: &Value,
+    origin: &Origin,
+    path: &str,
+)
+{
+    let (Some(base), Some(child)) = (base.as_object_mut(), child.as_object())
+    else
+    {
+        return;
+    };
+
+    for (key, value) in child
+    {
+        let field_path = join_field_path(path, key);
+        match base.get_mut(key)
+        {
+            //  Secret references replace the inherited value as a whole.
+            Some(base_value) if base_value.is_object()
+                && value.is_object()
+                && is_secret_reference(base_value) == false
+                && is_secret_reference(value) == false =>
+            {
+                merge(base_value, origins, value, origin, &field_path);
+            },
+            _ =>
+            {
+                let prefix = field_path.clone() + ".";
+                origins.retain(|path, _|
+                {
+                    *path != field_path && path.starts_with(&prefix) == false
+                });
+                record_origins(origins, value, origin, &field_path);
+                base.insert(key.clone(), value.clone());
+            },
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Records the origin of the value and of the values in it.
+//------------------------------------------------------------------------------
+fn record_origins
+(
+    origins: &mut BTreeMap<String, Origin>,
+    value: &Value,
+    origin: &Origin,
+    path: &str,
+)
+{
+    match value
+    {
+        Value::Object(map) if map.len() > 0 =>
+        {
+            for (key, value) in map
+            {
+                let field_path = join_field_path(path, key);
+                record_origins(origins, value, origin, &field_path);
+            }
+        },
+        _ =>
+        {
+            origins.insert(path.to_string(), origin.clone());
+        },
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Joins the key to the field path.
+//------------------------------------------------------------------------------
+fn join_field_path( path: &str, key: &str ) -> String
+{
+    if path.len() == 0
+    {
+        return key.to_string();
+    }
+    path.to_string() + "." + key
+}
+
+//------------------------------------------------------------------------------
+/// Finds the origin of the value at the field path, or of the closest value
+/// that contains it.
+///
+/// # Arguments
+///
+/// - `origins` - Origins keyed by the field path.
+/// - `path` - Field path, such as `connect_info.host` or `tunnels[0].host`.
+//------------------------------------------------------------------------------
+pub fn find_origin<'a>
+(
+    origins: &'a BTreeMap<String, Origin>,
+    path: &str,
+) -> Option<&'a Origin>
+{
+    let mut path = path;
+    loop
+    {
+        if let Some(origin) = origins.get(path)
+        {
+            return Some(origin);
+        }
+        match path.rfind(['.', '['])
+        {
+            Some(pos) => path = &path[..pos],
+            None => return None,
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Prints the origin of each value of the configuration.
+///
+/// # Arguments
+///
+/// - `origins` - Origins keyed by the field path.
+//------------------------------------------------------------------------------
+pub fn print_origins( origins: &BTreeMap<String, Origin> )
+{
+    let width = origins.keys().map(|path| path.len()).max().unwrap_or(0);
+    for (path, origin) in origins
+    {
+        println!("{:width$} : {}", path.green(), origin, width = width);
+    }
+}
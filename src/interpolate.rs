@@ -0,0 +1,319 @@
+//! Environment variables and secret references in configuration values.
+
+use crate::inherit::{ Origin, find_origin };
+
+use std::collections::{ BTreeMap, HashMap };
+use std::fmt;
+use std::path::{ Path, PathBuf };
+use std::process::{ Command, Stdio };
+
+use serde_json::Value;
+
+//------------------------------------------------------------------------------
+/// Fields whose commands are run by a shell, which expands the variables by
+/// itself, possibly on the remote server.
+//------------------------------------------------------------------------------
+const SHELL_FIELDS: [&str; 3] = ["build_command", "pre_deploy", "post_deploy"];
+
+//------------------------------------------------------------------------------
+/// Fields that accept secret references, matched by the last key of the field
+/// path.
+//------------------------------------------------------------------------------
+const SECRET_FIELDS: [&str; 3] =
+[
+    "password",
+    "db_password",
+    "db_root_password",
+];
+
+//------------------------------------------------------------------------------
+/// Kinds of secret references.
+//------------------------------------------------------------------------------
+const SECRET_KINDS: [&str; 2] = ["from_file", "from_command"];
+
+//------------------------------------------------------------------------------
+/// Error of resolving a value.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct InterpolateError
+{
+    /// Field path of the value.
+    pub field: String,
+
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for InterpolateError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "{}: {}", &self.field, &self.message)
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Checks whether the value is a secret reference, an object whose only key is
+/// `from_file` or `from_command`.
+//------------------------------------------------------------------------------
+pub fn is_secret_reference( value: &Value ) -> bool
+{
+    match value.as_object()
+    {
+        Some(map) if map.len() == 1 =>
+        {
+            SECRET_KINDS.contains(&map.keys().next().unwrap().as_str())
+        },
+        _ => false,
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Resolves `${VAR}` in string values and replaces the secret references
+/// `{ "from_file": "path" }` and `{ "from_command": "command" }` with the
+/// content of the file and the output of the command. The outputs of the
+/// commands are cached, so that a command shared by several entries is run
+/// only once.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct Interpolator
+{
+    outputs: HashMap<String, String>,
+}
+
+impl Interpolator
+{
+    //--------------------------------------------------------------------------
+    /// Resolves the values in the entry.
+    ///
+    /// # Arguments
+    ///
+    /// - `value` - Entry as a JSON object.
+    /// - `origins` - Origins of the values, used to resolve relative paths
+    ///   from the file that the reference is written in.
+    //--------------------------------------------------------------------------
+    pub fn resolve
+    (
+        &mut self,
+        value: &mut Value,
+        origins: &BTreeMap<String, Origin>,
+    ) -> Result<(), InterpolateError>
+    {
+        self.resolve_value(value, origins, "")
+    }
+
+    //--------------------------------------------------------------------------
+    /// Resolves the value and the values in it recursively.
+    //--------------------------------------------------------------------------
+    fn resolve_value
+    (
+        &mut self,
+        value: &mut Value,
+        origins: &BTreeMap<String, Origin>,
+        path: &str,
+    ) -> Result<(), InterpolateError>
+    {
+        if SHELL_FIELDS.contains(&path)
+        {
+            return Ok(());
+        }
+
+        match value
+        {
+            Value::String(s) =>
+            {
+                *s = expand_env(s).map_err(|message| InterpolateError
+                {
+                    field: path.to_string(),
+                    message,
+                })?;
+            },
+            Value::Array(values) =>
+            {
+                for (i, value) in values.iter_mut().enumerate()
+                {
+                    let path = format!("{}[{}]", path, i);
+                    self.resolve_value(value, origins, &path)?;
+                }
+            },
+            Value::Object(map) =>
+            {
+                if let Some(secret) = self.resolve_secret(map, origins, path)?
+                {
+                    *value = Value::String(secret);
+                    return Ok(());
+                }
+                for (key, value) in map.iter_mut()
+                {
+                    let path = if path.len() > 0
+                    {
+                        path.to_string() + "." + key
+                    }
+                    else
+                    {
+                        key.clone()
+                    };
+                    self.resolve_value(value, origins, &path)?;
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Resolves the object if it is a secret reference. Returns None if it is
+    /// not. Secret references are accepted only in the password fields.
+    //--------------------------------------------------------------------------
+    fn resolve_secret
+    (
+        &mut self,
+        map: &serde_json::Map<String, Value>,
+        origins: &BTreeMap<String, Origin>,
+        path: &str,
+    ) -> Result<Option<String>, InterpolateError>
+    {
+        if map.len() != 1
+        {
+            return Ok(None);
+        }
+        let (kind, reference) = map.iter().next().unwrap();
+        if SECRET_KINDS.contains(&kind.as_str()) == false
+        {
+            return Ok(None);
+        }
+        let key = path.rsplit('.').next().unwrap_or(path);
+        if SECRET_FIELDS.contains(&key) == false
+        {
+            return Err(InterpolateError
+            {
+                field: path.to_string(),
+                message: "secret references are only accepted in the \
+                    password fields".to_string(),
+            });
+        }
+
+        let error = |message| InterpolateError
+        {
+            field: path.to_string() + "." + kind,
+            message,
+        };
+        let reference = match reference
+        {
+            Value::String(reference) => expand_env(reference).map_err(error)?,
+            _ => return Err(error("must be a string".to_string())),
+        };
+        let secret = if kind == "from_file"
+        {
+            let origin = find_origin(origins, &(path.to_string() + "." + kind));
+            let file = resolve_file_path(&reference, origin);
+            std::fs::read_to_string(&file).map_err(|e|
+            {
+                error(format!("failed to read {}: {}", file.display(), e))
+            })?
+        }
+        else
+        {
+            self.run_command(&reference).map_err(error)?
+        };
+        Ok(Some(secret.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Runs the command with `sh -c` and returns its output. The standard input
+    /// and error are inherited, so that the command can prompt for a
+    /// passphrase.
+    //--------------------------------------------------------------------------
+    fn run_command( &mut self, command: &str ) -> Result<String, String>
+    {
+        if let Some(output) = self.outputs.get(command)
+        {
+            return Ok(output.clone());
+        }
+
+        let output = Command::new("sh")
+            .args(["-c", command])
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| format!("failed to run {}: {}", command, e))?;
+        if output.status.success() == false
+        {
+            return Err(format!("{} exited with {}", command, output.status));
+        }
+
+        let output = String::from_utf8_lossy(&output.stdout).to_string();
+        self.outputs.insert(command.to_string(), output.clone());
+        Ok(output)
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Expands `${VAR}` in the string with the environment variables. `$${` is
+/// kept as a literal `${`, and any other `$` is left as is.
+///
+/// # Arguments
+///
+/// - `s` - String to expand.
+//------------------------------------------------------------------------------
+fn expand_env( s: &str ) -> Result<String, String>
+{
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(pos) = rest.find("${")
+    {
+        //  `$${` is an escaped `${`.
+        if rest[..pos].ends_with('$')
+        {
+            expanded += &rest[..pos - 1];
+            expanded += "${";
+            rest = &rest[pos + 2..];
+            continue;
+        }
+
+        expanded += &rest[..pos];
+        let end = match rest[pos..].find('}')
+        {
+            Some(end) => pos + end,
+            None => return Err(format!("unterminated ${{ in {}", s)),
+        };
+        let name = &rest[pos + 2..end];
+        match std::env::var(name)
+        {
+            Ok(value) => expanded += &value,
+            Err(_) =>
+            {
+                return Err
+                (
+                    format!("environment variable {} is not set", name),
+                );
+            },
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded += rest;
+    Ok(expanded)
+}
+
+//------------------------------------------------------------------------------
+/// Resolves the path of a `from_file` reference. `~/` is the home directory,
+/// and a relative path is relative to the directory of the file that the
+/// reference is written in.
+//------------------------------------------------------------------------------
+fn resolve_file_path( reference: &str, origin: Option<&Origin> ) -> PathBuf
+{
+    if let Some(rest) = reference.strip_prefix("~/")
+    {
+        if let Ok(home) = std::env::var("HOME")
+        {
+            return Path::new(&home).join(rest);
+        }
+    }
+
+    let path = Path::new(reference);
+    match origin.and_then(|origin| Path::new(&origin.file).parent())
+    {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
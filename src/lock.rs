@@ -0,0 +1,235 @@
+//! Deploy lock placed on the remote server to prevent concurrent deploys.
+
+use crate::ssh_config::SshConfig;
+use crate::manifest::get_operator;
+use crate::util::{
+    get_session,
+    print_sep,
+    print_dry_run_command,
+    shell_quote,
+    confirm,
+};
+
+use chrono::Local;
+use colored::Colorize;
+use openssh::{ Session, Stdio };
+use serde::{ Deserialize, Serialize };
+
+//------------------------------------------------------------------------------
+/// Record of who holds the deploy lock.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeployLock
+{
+    pub holder: String,
+    pub host: String,
+    pub command: String,
+    pub started_at: String,
+}
+
+impl DeployLock
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new lock for the current operator.
+    ///
+    /// # Arguments
+    ///
+    /// - `command` - Subcommand that takes the lock.
+    //--------------------------------------------------------------------------
+    pub fn new( command: &str ) -> Self
+    {
+        Self
+        {
+            holder: get_operator(),
+            host: get_hostname(),
+            command: command.to_string(),
+            started_at: Local::now().to_rfc3339(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Prints the lock.
+    //--------------------------------------------------------------------------
+    pub fn print( &self )
+    {
+        println!("{} : {}@{}", "Locked by  ".red(), &self.holder, &self.host);
+        println!("{} : {}", "Command    ".red(), &self.command);
+        println!("{} : {}", "Started at ".red(), &self.started_at);
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the host name of the local machine.
+//------------------------------------------------------------------------------
+fn get_hostname() -> String
+{
+    std::process::Command::new("hostname")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| name.len() > 0)
+        .unwrap_or("unknown".to_string())
+}
+
+//------------------------------------------------------------------------------
+/// Reads the deploy lock from the remote server.
+//------------------------------------------------------------------------------
+async fn read_lock
+(
+    session: &Session,
+    config: &SshConfig,
+) -> Option<DeployLock>
+{
+    let output = session
+        .command("cat")
+        .arg(config.lock_path())
+        .output()
+        .await
+        .ok()?;
+    if output.status.success() == false
+    {
+        return None;
+    }
+    Some(serde_json::from_slice(&output.stdout).unwrap_or_default())
+}
+
+//------------------------------------------------------------------------------
+/// Takes the deploy lock on the remote server. If the lock is already held,
+/// prints the holder and returns false.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+/// - `command` - Subcommand that takes the lock.
+//------------------------------------------------------------------------------
+pub async fn acquire_lock
+(
+    session: &Session,
+    config: &SshConfig,
+    command: &str,
+) -> bool
+{
+    //  The lock file is created with noclobber so that only one of concurrent
+    //  deploys can take it.
+    let lock = serde_json::to_string_pretty(&DeployLock::new(command)).unwrap();
+    let status = session
+        .shell(format!
+        (
+            "set -C && printf '%s\\n' {} > {}",
+            shell_quote(&lock),
+            shell_quote(&config.lock_path()),
+        ))
+        .stderr(Stdio::null())
+        .status()
+        .await;
+    if let Ok(status) = status
+    {
+        if status.success()
+        {
+            return true;
+        }
+    }
+
+    print_sep();
+    match read_lock(session, config).await
+    {
+        Some(lock) =>
+        {
+            println!
+            (
+                "{} : {} is being deployed by someone else.",
+                "Error".red(),
+                &config.project(),
+            );
+            lock.print();
+            println!
+            (
+                "Run `resm unlock {}` if the lock is stale.",
+                &config.project(),
+            );
+        },
+        None =>
+        {
+            println!
+            (
+                "{} : Failed to create the lock file {}.",
+                "Error".red(),
+                &config.lock_path(),
+            );
+        },
+    }
+    print_sep();
+    false
+}
+
+//------------------------------------------------------------------------------
+/// Releases the deploy lock on the remote server.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub async fn release_lock( session: &Session, config: &SshConfig )
+{
+    session
+        .command("rm")
+        .args(["-f", &config.lock_path()])
+        .output()
+        .await
+        .unwrap();
+}
+
+//------------------------------------------------------------------------------
+/// Removes a stale deploy lock after confirmation.
+///
+/// # Arguments
+///
+/// - `project` - Project name.
+/// - `config` - SSH configuration.
+/// - `dry_run` - Whether to only print what would be done.
+//------------------------------------------------------------------------------
+pub async fn unlock( project: &str, config: &SshConfig, dry_run: bool )
+{
+    let session = get_session(project).await;
+    let lock = read_lock(&session, config).await;
+
+    print_sep();
+    println!("{} : {}", "Project    ".green(), &config.project());
+    println!("{} : {}", "Environment".green(), &config.environment());
+    println!("{} : {}", "Lock path  ".green(), &config.lock_path());
+    let lock = match lock
+    {
+        Some(lock) => lock,
+        None =>
+        {
+            println!("Not locked.");
+            print_sep();
+            session.close().await.unwrap();
+            return;
+        },
+    };
+    lock.print();
+    print_sep();
+
+    if dry_run
+    {
+        println!("{}", "Dry run. No changes will be made.".yellow());
+        print_dry_run_command("rm", &["-f", &config.lock_path()]);
+        session.close().await.unwrap();
+        return;
+    }
+
+    if confirm("Are you sure you want to remove the lock?") == false
+    {
+        println!("Canceled.");
+        session.close().await.unwrap();
+        return;
+    }
+
+    release_lock(&session, config).await;
+    session.close().await.unwrap();
+    println!("Done.");
+}
@@ -12,6 +12,7 @@
 //! 
 //!         "remote_path": "path/to/remote/project/root",
 //!         "remote_cache_path": "path/to/remote/project/cache",
+//!         "remote_cache_mode": "775",
 //! 
 //!         "git_path": "path/to/git/repo",
 //!         "git_src_path": "path/to/git/src",
@@ -42,6 +43,34 @@
 //!                 "user": "user",
 //!                 "identity_file": "path/to/key_file"
 //!             }
+//!         ],
+//! 
+//!         "release_mode": false,
+//!         "keep_releases": 5,
+//! 
+//!         "exclude": ["node_modules", ".env"],
+//!         "preserve": [".env", "storage/"],
+//!
+//!         "build_command": "npm ci && npm run build",
+//!         "artifact_path": "path/to/git/repo/dist",
+//!
+//!         "pre_deploy":
+//!         {
+//!             "local": ["npm run build"],
+//!             "remote": ["php artisan down"]
+//!         },
+//!         "post_deploy":
+//!         {
+//!             "remote": ["composer install", "php artisan migrate --force"]
+//!         },
+//!         "permissions":
+//!         [
+//!             { "path": "bin/*", "mode": "755" },
+//!             {
+//!                 "path": "storage/**",
+//!                 "owner": "www-data",
+//!                 "group": "www-data"
+//!             }
 //!         ]
 //!     }
 //! ]
@@ -53,10 +82,13 @@
 //! 
 //! - project (*): Optional project name
 //! - environment (*): Optional environment name
+//! - extends: Key (`project_environment`) of the entry to inherit values from
 //! - remote_path: Reference path to be operated in the destination server
 //!                (absolute path)
 //! - remote_cache_path: Path to the cache directory in the destination server
 //!                      (absolute path)
+//! - remote_cache_mode: Mode of the cache directory created by `clear`
+//!                      (default: 777)
 //! - git_path: Path to the git repository (absolute path)
 //! - git_src_path: Path to the source directory in the git repository
 //!                 (absolute path)
@@ -77,7 +109,108 @@
 //!     - identity_file: Path to the identity file (absolute path)
 //! - tunnels: Information on the step server to be passed through when
 //!            connecting (array of connect_info)
-//! 
+//! - release_mode: Whether to deploy into `<remote_path>.releases/<timestamp>`
+//!                 and switch `remote_path` as a symbolic link
+//! - keep_releases: Number of releases to keep in release mode (default: 5)
+//! - exclude: Globs of files not to be uploaded by `replace` (array of string).
+//!            `replace --delta --delete` does not delete the remote files
+//!            matching them either. Use `preserve` to keep them on a full
+//!            replace.
+//! - preserve: Globs of remote files that `replace` never deletes or overwrites
+//!             (array of string)
+//! - build_command: Command to be run in `git_path` by `replace` before
+//!                  collecting the files to upload
+//! - artifact_path: Path to the directory whose files are uploaded by `replace`
+//!                  instead of `git_src_path` (absolute path)
+//! - pre_deploy: Commands to be run before `replace` and `patch` upload files
+//!     - local: Commands run in `git_path` on the local machine
+//!              (array of string)
+//!     - remote: Commands run in `remote_path` on the destination server
+//!               (array of string)
+//! - post_deploy: Commands to be run after `replace` and `patch` upload files
+//!                (same format as pre_deploy)
+//! - permissions: Rules applied after `replace` and `patch` upload files
+//!                (array)
+//!     - path (*): Glob of the remote paths relative to `remote_path`
+//!     - mode: Mode passed to `chmod`, such as `755` or `u+x`
+//!     - owner: Owner passed to `chown`
+//!     - group: Group passed to `chown`
+//!
+//! # Inheritance
+//!
+//! An entry with `extends` starts from the resolved values of the entry with
+//! that key, which can be in another file, and overrides only the fields it
+//! sets. Objects such as `connect_info` are merged field by field, and other
+//! values, including arrays, replace the inherited ones. Set `environment` in
+//! each entry, since it is inherited too and the key must be unique.
+//!
+//! ```json
+//! [
+//!     {
+//!         "project": "app",
+//!         "environment": "base",
+//!         "git_path": "path/to/git/repo",
+//!         "connect_info": { "host": "host name", "user": "deploy" }
+//!     },
+//!     {
+//!         "project": "app",
+//!         "environment": "prod",
+//!         "extends": "app_base",
+//!         "connect_info": { "host": "prod host name" }
+//!     }
+//! ]
+//! ```
+//!
+//! # TOML and YAML
+//!
+//! Files with the `.toml`, `.yaml` and `.yml` extensions are loaded into the
+//! same entries as the JSON files, so comments can be written in them. In TOML,
+//! each entry is a `[[entries]]` table, and in YAML, the document is a list of
+//! entries. Entries in different formats can extend each other.
+//!
+//! ```toml
+//! # Each entry is a `[[entries]]` table.
+//! [[entries]]
+//! project = "project name"
+//! environment = "dev"
+//! remote_path = "path/to/remote/project/root"
+//!
+//! [entries.connect_info]
+//! host = "host name"
+//! user = "user"
+//! ```
+//!
+//! ```yaml
+//! # The document is a list of entries.
+//! - project: project name
+//!   environment: dev
+//!   remote_path: path/to/remote/project/root
+//!   connect_info:
+//!     host: host name
+//!     user: user
+//! ```
+//!
+//! # Environment variables and secrets
+//!
+//! When the files are loaded, `${VAR}` in string values is replaced with the
+//! environment variable (write `$${` for a literal `${`). In the password
+//! fields (`db_password`, `db_root_password` and `password` of `connect_info`
+//! and `tunnels`), the objects `{ "from_file": "path" }` and `{ "from_command":
+//! "command" }` are replaced with the content of the file and the output of the
+//! command run with `sh -c`, without the trailing newline. A relative
+//! `from_file` path is relative to the file it is written in. This way,
+//! passwords do not have to be written in the configuration files. A secret
+//! reference replaces an inherited value as a whole. `build_command`,
+//! `pre_deploy` and `post_deploy` are left as is, since their commands are
+//! expanded by the shell. An entry whose value cannot be resolved is reported
+//! and skipped. Note that `show` prints the resolved values.
+//!
+//! ```json
+//! "db_user": "${DB_USER}",
+//! "db_password": { "from_file": "~/.secrets/db_password" },
+//! "db_root_password": { "from_command": "pass show db/root" }
+//! ```
+//!
 //! 
 //! # Commands
 //! 
@@ -91,7 +224,37 @@
 //! 
 //! ## show
 //! 
-//! Shows the project setting.
+//! Shows the project setting with the inherited values resolved. With
+//! `--origins`, the entry and file that each value comes from are also shown.
+//! 
+//! ## validate
+//! 
+//! Checks the configuration files and prints each problem with the file, line,
+//! entry and field, then exits with status 1 if any problem was found. Parse
+//! errors, invalid values, fields unknown to the schema, invalid globs in
+//! `exclude`, `preserve` and `permissions`, duplicate `project_environment`
+//! keys (the last entry takes precedence), paths that must be absolute and
+//! identity files that do not exist are reported. With `--command
+//! <subcommand>`, the fields required by the subcommand, such as `db_name` for
+//! `backup-db`, are checked as well. The same problems are printed whenever the
+//! files are loaded by other commands.
+//! 
+//! ```sh
+//! $ resm validate [project] --command backup-db
+//! ```
+//! 
+//! ## schema
+//! 
+//! Prints the JSON Schema of the configuration files, which is generated from
+//! the configuration types, so that editors can complete and check the fields.
+//! The schema describes the JSON files, whose root is the array of entries.
+//! Secret references are accepted only in the password fields. Only `project`
+//! and `environment` are required, since the other fields can be inherited
+//! through `extends`, and unknown fields are reported.
+//! 
+//! ```sh
+//! $ resm schema > resm.schema.json
+//! ```
 //! 
 //! ## replace
 //! 
@@ -99,14 +262,111 @@
 //! project directory. In short, it is useful when you want to perform a full
 //! update of an application.
 //! 
+//! Files ignored by `.gitignore`, `.git/info/exclude` or a `.resmignore` file
+//! (same syntax as `.gitignore`), and files matching the `exclude` globs are
+//! not uploaded.
+//! 
+//! With `--ref <tag|branch|sha>`, the files are read from the tree of the
+//! specified commit (restricted to `git_src_path`) instead of the working tree,
+//! so that deploys are reproducible from a named revision.
+//! 
+//! With `--delta`, only files that differ from the remote files (compared by
+//! size and modification time, or by hash with `--checksum`) are uploaded,
+//! and `--delete` removes remote files that no longer exist locally. Remote
+//! files that are skipped locally by the ignore files or the `exclude` globs
+//! are never deleted. A summary of added, changed and deleted files is shown
+//! before the confirmation.
+//! 
+//! Files are uploaded concurrently over the SFTP session, 8 at a time by
+//! default. Use `-j/--jobs <N>` (also available for `patch`) to change the
+//! number, for example to raise it when connecting through a bastion with high
+//! latency.
+//!
+//! With `--archive tar.gz` (or `--archive zip`, `--zip` for short), the files
+//! are packed into an archive in the local temporary directory, uploaded in one
+//! piece and extracted with `tar` (or `unzip`) into a staging directory next to
+//! the target directory (`<target>.resm-staging`), which the remote user must
+//! be able to create. The extractor is checked to exist on the remote server,
+//! and the archive is extracted before anything is removed, so the deploy is
+//! aborted with the target untouched if the upload or the extraction fails. The
+//! extracted files are then copied into the target directory and the staging
+//! directory is removed.
+//!
+//! Remote files matching the `preserve` globs (relative to `remote_path`), such
+//! as `.env` or user uploads, are left untouched: only the other files are
+//! removed, local files matching them are not uploaded, and in release mode
+//! they are copied from the current release into the new one. The preserved
+//! paths are listed before the confirmation.
+//!
+//! If `build_command` is set, it is run locally first and the deploy is aborted
+//! when it fails. If `artifact_path` is set, the files in that directory are
+//! uploaded instead of `git_src_path`. The `.gitignore` rules are not applied
+//! to the artifact directory, since build outputs are usually ignored by git.
+//! `--ref` cannot be used together with `build_command` or `artifact_path`.
+//!
+//! In release mode, files are uploaded into a new release directory and the
+//! `remote_path` symbolic link is switched to it after the upload is complete,
+//! so the application is never served half-deployed. For example, with
+//! `remote_path` set to `/var/www/app`, releases are placed in
+//! `/var/www/app.releases/<timestamp>`. If `remote_path` is an existing
+//! directory rather than a symbolic link, the deploy is refused until it is
+//! moved into the releases directory and linked. If any file fails to upload,
+//! or the current release cannot be copied into the new one, the deploy is
+//! aborted without switching the link. The link is replaced atomically with GNU
+//! `mv -T`. On servers whose `mv` does not support it, such as BSD and macOS,
+//! `ln -sfn` is used instead, which is not atomic.
+//! 
+//! ## rollback
+//! 
+//! Moves the `remote_path` symbolic link back to the previous release.
+//! 
+//! It is refused if `remote_path` does not point to one of the releases, for
+//! example when a parent directory is a symbolic link.
+//! 
 //! ## patch
 //! 
 //! Uploads only specified files in the local repository to the remote server.
+//! By default, files changed in the working tree are uploaded. With
+//! `--from <rev> [--to <rev>]`, files added or modified between the revisions
+//! are uploaded from the `to` revision (`HEAD` by default).
+//! 
+//! Added, modified, renamed and deleted files are listed separately, and
+//! renamed and deleted files are moved or removed on the remote server.
+//! 
+//! ## status
+//! 
+//! Shows the revision recorded in the deploy manifest (`.resm-deploy.json`
+//! under `remote_path`, written by `replace` and `patch`) and compares it with
+//! the local HEAD.
+//! 
+//! ## diff
+//! 
+//! Compares the local source tree with the remote directory and reports files
+//! only in local, only in remote and with different content. Files of the same
+//! size are compared by hash, and files that cannot be read are reported as
+//! unreadable. With `-u`, unified diffs are printed for text files.
+//! 
+//! ## pull
+//! 
+//! Downloads the specified files or directories (relative to `remote_path`)
+//! into the matching place under `git_src_path`, so that hotfixes made on the
+//! server can be reviewed and committed with git. Paths containing `..` and
+//! symbolic links pointing outside `remote_path` are rejected, and a file that
+//! fails to download is reported without stopping the others.
 //! 
 //! ## clear
 //! 
 //! Clears the remote cache directory.
 //! 
+//! ## unlock
+//! 
+//! Removes a stale deploy lock.
+//! 
+//! `replace`, `patch`, `rollback` and `clear` take a lock file next to
+//! `remote_path` (for example `/var/www/app.resm-lock`) that records the
+//! operator, host, command and start time, and release it when they finish.
+//! While the lock is held, these commands refuse to run for the same project.
+//! 
 //! ## backup
 //! 
 //! Backs up the remote directory.
@@ -114,20 +374,100 @@
 //! ## backup-db
 //! 
 //! Backs up the database.
+//! 
+//! # Deploy hooks
+//! 
+//! `replace` and `patch` run the `pre_deploy` commands after the confirmation
+//! and before any remote change, and the `post_deploy` commands after the
+//! upload (and the release switch in release mode) and before the cache is
+//! cleared. Local commands are run first, then remote commands. Their output is
+//! streamed, and the run is aborted as soon as a command exits with a non-zero
+//! status.
+//! 
+//! # Permissions
+//!
+//! Uploaded files keep the permission bits of the local files (or the
+//! executable bit recorded in git for `--ref` and revision ranges). After the
+//! upload, and before the release switch in release mode, `replace` and
+//! `patch` apply the `permissions` rules in order with `chmod` and `chown`, so
+//! later rules win. The globs are matched like `exclude` against the paths
+//! under the deployed directory; use `dir/**` to match the contents of a
+//! directory. The deploy is aborted if a rule is invalid or a command fails.
+//!
+//! # Safety guard
+//!
+//! Before removing anything on the remote server, the paths are validated and
+//! the command is refused with an error for the project if a path is empty,
+//! relative, contains `..`, is `/` or a home directory, or is outside
+//! `remote_path` (the releases directory in release mode and the staging
+//! directory of `--archive` are also allowed). In `/tmp`, only the temporary
+//! zip file of `backup` can be removed. This applies to `replace`, `patch`,
+//! `rollback`, `clear` and `backup`, so `remote_cache_path` must be inside
+//! `remote_path`.
+//!
+//! # Progress
+//!
+//! Uploads, downloads and database dumps show a progress bar with the number
+//! of files, the transferred bytes, the transfer rate and the estimated time
+//! left. When stdout is not a terminal, a log line with the same information is
+//! printed every 5 seconds instead, followed by a summary line at the end.
+//!
+//! # Dry run
+//! 
+//! With the global `--dry-run` option, `replace`, `rollback`, `patch`, `pull`,
+//! `clear`, `unlock`, `backup` and `backup-db` print the paths to be removed,
+//! the files to be transferred and the commands to be run, without making any
+//! changes.
 
 #![allow(dead_code)]
 
 mod connect_info;
 mod ssh_config;
+mod inherit;
+mod interpolate;
+mod config_format;
 mod generate;
 mod upload;
+mod archive;
+mod release;
+mod source;
+mod changeset;
+mod manifest;
+mod hooks;
+mod permissions;
+mod lock;
+mod preserve;
+mod safety;
+mod diff;
+mod pull;
+mod sync;
+mod transfer;
+mod progress;
 mod backup;
+mod validate;
+mod schema;
 mod util;
 
 use generate::generate_ssh_config;
-use upload::{ upload_all, upload_patch, clear_cache };
+use upload::{
+    ReplaceOptions,
+    PatchOptions,
+    upload_all,
+    upload_patch,
+    clear_cache,
+    print_clear_cache_plan,
+};
+use release::rollback;
+use archive::ArchiveFormat;
+use lock::{ acquire_lock, release_lock, unlock };
+use manifest::status;
+use diff::diff;
+use pull::pull;
 use backup::{ backup, backup_db };
-use util::{ load_json, get_session };
+use inherit::print_origins;
+use validate::{ COMMANDS, validate };
+use schema::print_schema;
+use util::{ load_json, get_matched_configs, get_session };
 
 use std::env;
 
@@ -155,6 +495,14 @@ struct Cli
         default_value = "",
     )]
     env_path: String,
+
+    /// Print what would be done without making any changes on the remote
+    /// server.
+    #[clap(
+        long,
+        global = true,
+    )]
+    dry_run: bool,
 }
 
 //------------------------------------------------------------------------------
@@ -177,8 +525,32 @@ enum Subcommands
             required = true,
         )]
         project: String,
+
+        /// Show the entry and file that each value comes from.
+        #[clap(
+            long,
+        )]
+        origins: bool,
+    },
+
+    /// Validate the configuration files.
+    Validate
+    {
+        /// Project name. All projects are validated if omitted.
+        project: Option<String>,
+
+        /// Check the fields required by the subcommand.
+        #[clap(
+            short = 'c',
+            long,
+            value_parser = COMMANDS,
+        )]
+        command: Option<String>,
     },
 
+    /// Print the JSON Schema of the configuration files.
+    Schema,
+
     /// Replace the project directory in the destination server with the local
     /// project directory.
     Replace
@@ -197,11 +569,68 @@ enum Subcommands
         )]
         target_path: String,
 
-        /// Whether to compress files when uploading.
+        /// Upload the files in an archive (`tar.gz` or `zip`) and extract it
+        /// on the destination server.
+        #[clap(
+            long,
+            value_parser = ["tar.gz", "zip"],
+            conflicts_with = "delta",
+        )]
+        archive: Option<String>,
+
+        /// Same as `--archive zip`.
         #[clap(
             long,
+            conflicts_with_all = ["delta", "archive"],
         )]
         zip: bool,
+
+        /// Upload only files that differ from the remote files instead of
+        /// removing the remote directory.
+        #[clap(
+            long,
+        )]
+        delta: bool,
+
+        /// Delete remote files that do not exist locally (with `--delta`).
+        #[clap(
+            long,
+            requires = "delta",
+        )]
+        delete: bool,
+
+        /// Compare files by hash instead of size and modification time (with
+        /// `--delta`).
+        #[clap(
+            long,
+            requires = "delta",
+        )]
+        checksum: bool,
+
+        /// Tag, branch or commit hash to upload instead of the working tree.
+        #[clap(
+            long = "ref",
+            default_value = "",
+        )]
+        revision: String,
+
+        /// Number of files uploaded at the same time.
+        #[clap(
+            short = 'j',
+            long,
+            default_value = "8",
+        )]
+        jobs: usize,
+    },
+
+    /// Move the remote path back to the previous release.
+    Rollback
+    {
+        /// Project name.
+        #[clap(
+            required = true,
+        )]
+        project: String,
     },
 
     /// Upload only specified files in the local repository to the remote
@@ -219,8 +648,84 @@ enum Subcommands
             short = 'f',
             long,
             default_value = "",
+            conflicts_with = "from",
         )]
         patch_file: String,
+
+        /// Upload the files changed since this revision (tag, branch or
+        /// commit hash).
+        #[clap(
+            long,
+            default_value = "",
+        )]
+        from: String,
+
+        /// Upload the files changed up to this revision (with `--from`).
+        #[clap(
+            long,
+            default_value = "HEAD",
+        )]
+        to: String,
+
+        /// Number of files uploaded at the same time.
+        #[clap(
+            short = 'j',
+            long,
+            default_value = "8",
+        )]
+        jobs: usize,
+    },
+
+    /// Show the deployed revision and compare it with the local HEAD.
+    Status
+    {
+        /// Project name.
+        #[clap(
+            required = true,
+        )]
+        project: String,
+    },
+
+    /// Compare the local source tree with the remote directory.
+    Diff
+    {
+        /// Project name.
+        #[clap(
+            required = true,
+        )]
+        project: String,
+
+        /// Relative path from the project directory that you want to compare.
+        #[clap(
+            short = 't',
+            long,
+            default_value = "",
+        )]
+        target_path: String,
+
+        /// Whether to print unified diffs for text files.
+        #[clap(
+            short = 'u',
+            long,
+        )]
+        unified: bool,
+    },
+
+    /// Download remote files or directories into the local source directory.
+    Pull
+    {
+        /// Project name.
+        #[clap(
+            required = true,
+        )]
+        project: String,
+
+        /// Relative paths from the remote path to download.
+        #[clap(
+            required = true,
+            num_args = 1..,
+        )]
+        paths: Vec<String>,
     },
 
     /// Clear the remote cache directory.
@@ -233,6 +738,16 @@ enum Subcommands
         project: String,
     },
 
+    /// Remove a stale deploy lock.
+    Unlock
+    {
+        /// Project name.
+        #[clap(
+            required = true,
+        )]
+        project: String,
+    },
+
     /// Back up the remote directory.
     Backup
     {
@@ -287,12 +802,29 @@ async fn main()
     {
         env::var("HOME").unwrap_or("".to_string()) + "/env"
     };
+    match &cli.subcommand
+    {
+        Subcommands::Validate { project, command } =>
+        {
+            let project = project.as_deref();
+            let valid = validate(&env_path, project, command.as_deref());
+            std::process::exit(if valid { 0 } else { 1 });
+        },
+        Subcommands::Schema =>
+        {
+            print_schema();
+            return;
+        },
+        _ => {},
+    }
     let config_entries = load_json(&env_path);
 
     //  Executes subcommand.
+    let dry_run = cli.dry_run;
     match cli.subcommand
     {
         Subcommands::Init => generate_ssh_config(&config_entries),
+        Subcommands::Validate { .. } | Subcommands::Schema => {},
         Subcommands::List =>
         {
             let keys = config_entries.keys();
@@ -301,162 +833,136 @@ async fn main()
                 println!("{}", key);
             }
         },
-        Subcommands::Show { project } =>
+        Subcommands::Show { project, origins } =>
         {
-            if let Some(config) = config_entries.get(&project)
+            for (_, config) in get_matched_configs(&config_entries, &project)
             {
                 println!("{}", serde_json::to_string_pretty(&config).unwrap());
-            }
-            else
-            {
-                let mut found = false;
-                for (key, value) in config_entries.iter()
+                if origins
                 {
-                    if key.starts_with(&project)
-                    {
-                        println!
-                        (
-                            "{}",
-                            serde_json::to_string_pretty(&value).unwrap()
-                        );
-                        found = true;
-                    }
+                    print_origins(config.origins());
                 }
-
-                if !found
-                {
-                    println!("Project not found.");
-                }
-                return;
             }
         },
-        Subcommands::Replace { project, target_path, zip } =>
+        Subcommands::Replace
+        {
+            project,
+            target_path,
+            archive,
+            zip,
+            delta,
+            delete,
+            checksum,
+            revision,
+            jobs,
+        } =>
         {
-            if let Some(config) = config_entries.get(&project)
+            let archive = if zip
             {
-                upload_all(&project, config, target_path, zip).await;
+                Some("zip".to_string())
             }
             else
             {
-                let mut found = false;
-                for (key, value) in config_entries.iter()
-                {
-                    if key.starts_with(&project)
-                    {
-                        upload_all(&key, value, target_path.clone(), zip).await;
-                        found = true;
-                    }
-                }
-
-                if !found
-                {
-                    println!("Project not found.");
-                }
-                return;
+                archive
+            };
+            let options = ReplaceOptions
+            {
+                target_path,
+                archive: archive
+                    .and_then(|name| ArchiveFormat::from_name(&name)),
+                delta,
+                delete,
+                checksum,
+                revision,
+                jobs,
+                dry_run,
+            };
+            for (key, config) in get_matched_configs(&config_entries, &project)
+            {
+                upload_all(key, config, &options).await;
             }
         },
-        Subcommands::Patch { project, patch_file } =>
+        Subcommands::Rollback { project } =>
         {
-            if let Some(config) = config_entries.get(&project)
+            for (key, config) in get_matched_configs(&config_entries, &project)
             {
-                upload_patch(&project, config, patch_file).await;
+                rollback(key, config, dry_run).await;
             }
-            else
+        },
+        Subcommands::Patch { project, patch_file, from, to, jobs } =>
+        {
+            let options = PatchOptions
             {
-                let mut found = false;
-                for (key, value) in config_entries.iter()
-                {
-                    if key.starts_with(&project)
-                    {
-                        upload_patch(&key, value, patch_file.clone()).await;
-                        found = true;
-                    }
-                }
-
-                if !found
-                {
-                    println!("Project not found.");
-                }
-                return;
+                patch_file,
+                from,
+                to,
+                jobs,
+                dry_run,
+            };
+            for (key, config) in get_matched_configs(&config_entries, &project)
+            {
+                upload_patch(key, config, &options).await;
             }
         },
-        Subcommands::Clear { project } =>
+        Subcommands::Status { project } =>
         {
-            if let Some(config) = config_entries.get(&project)
+            for (key, config) in get_matched_configs(&config_entries, &project)
             {
-                let session = get_session(&project).await;
-                clear_cache(&session, &config.remote_cache_path()).await;
-                session.close().await.unwrap();
+                status(key, config).await;
             }
-            else
+        },
+        Subcommands::Diff { project, target_path, unified } =>
+        {
+            for (key, config) in get_matched_configs(&config_entries, &project)
             {
-                let mut found = false;
-                for (key, value) in config_entries.iter()
-                {
-                    if key.starts_with(&project)
-                    {
-                        let session = get_session(&key).await;
-                        clear_cache(&session, &value.remote_cache_path()).await;
-                        session.close().await.unwrap();
-                        found = true;
-                    }
-                }
-
-                if !found
-                {
-                    println!("Project not found.");
-                }
-                return;
+                diff(key, config, &target_path, unified).await;
             }
-        }
-        Subcommands::Backup { project, target_path } =>
+        },
+        Subcommands::Pull { project, paths } =>
         {
-            if let Some(config) = config_entries.get(&project)
+            for (key, config) in get_matched_configs(&config_entries, &project)
             {
-                backup(&project, config, target_path).await;
+                pull(key, config, &paths, dry_run).await;
             }
-            else
+        },
+        Subcommands::Clear { project } =>
+        {
+            for (key, config) in get_matched_configs(&config_entries, &project)
             {
-                let mut found = false;
-                for (key, value) in config_entries.iter()
+                if dry_run
                 {
-                    if key.starts_with(&project)
-                    {
-                        backup(&key, value, target_path.clone()).await;
-                        found = true;
-                    }
+                    print_clear_cache_plan(config);
+                    continue;
                 }
 
-                if !found
+                let session = get_session(key).await;
+                if acquire_lock(&session, config, "clear").await
                 {
-                    println!("Project not found.");
+                    clear_cache(&session, config).await;
+                    release_lock(&session, config).await;
                 }
-                return;
+                session.close().await.unwrap();
             }
         },
-        Subcommands::BackupDb { project, target_tables } =>
+        Subcommands::Unlock { project } =>
         {
-            if let Some(config) = config_entries.get(&project)
+            for (key, config) in get_matched_configs(&config_entries, &project)
             {
-                backup_db(&project, config, target_tables).await;
+                unlock(key, config, dry_run).await;
             }
-            else
+        },
+        Subcommands::Backup { project, target_path } =>
+        {
+            for (key, config) in get_matched_configs(&config_entries, &project)
             {
-                let mut found = false;
-                for (key, value) in config_entries.iter()
-                {
-                    if key.starts_with(&project)
-                    {
-                        backup_db(&key, value, target_tables.clone()).await;
-                        found = true;
-                    }
-                }
-
-                if !found
-                {
-                    println!("Project not found.");
-                }
-                return;
+                backup(key, config, target_path.clone(), dry_run).await;
+            }
+        },
+        Subcommands::BackupDb { project, target_tables } =>
+        {
+            for (key, config) in get_matched_configs(&config_entries, &project)
+            {
+                backup_db(key, config, target_tables.clone(), dry_run).await;
             }
         },
     }
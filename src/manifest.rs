@@ -0,0 +1,237 @@
+//! Deploy manifest recorded on the remote server.
+
+use crate::ssh_config::SshConfig;
+use crate::util::{ get_sftp_session, print_sep };
+
+use std::collections::BTreeMap;
+
+use chrono::Local;
+use colored::Colorize;
+use git2::{ Oid, Repository, StatusOptions };
+use openssh_sftp_client::fs::Fs;
+use serde::{ Deserialize, Serialize };
+
+//------------------------------------------------------------------------------
+/// File name of the deploy manifest placed under the remote path.
+//------------------------------------------------------------------------------
+pub const MANIFEST_FILE: &str = ".resm-deploy.json";
+
+//------------------------------------------------------------------------------
+/// Record of what was deployed to the remote server.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeployManifest
+{
+    pub command: String,
+    pub commit: Option<String>,
+    pub branch: Option<String>,
+    pub dirty: bool,
+    pub operator: String,
+    pub timestamp: String,
+
+    /// Map from paths relative to the remote path to SHA-256 hashes.
+    pub files: BTreeMap<String, String>,
+}
+
+impl DeployManifest
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new manifest for the local HEAD.
+    ///
+    /// # Arguments
+    ///
+    /// - `config` - SSH configuration.
+    /// - `command` - Subcommand that deployed the files.
+    //--------------------------------------------------------------------------
+    pub fn new( config: &SshConfig, command: &str ) -> Self
+    {
+        let (commit, branch, dirty) = get_local_head(config);
+        Self
+        {
+            command: command.to_string(),
+            commit,
+            branch,
+            dirty,
+            operator: get_operator(),
+            timestamp: Local::now().to_rfc3339(),
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the commit hash and branch name of the local HEAD, and whether the
+/// working tree has uncommitted changes.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub fn get_local_head
+(
+    config: &SshConfig,
+) -> (Option<String>, Option<String>, bool)
+{
+    let repo = match Repository::open(config.git_path())
+    {
+        Ok(repo) => repo,
+        Err(_) => return (None, None, false),
+    };
+    let head = match repo.head()
+    {
+        Ok(head) => head,
+        Err(_) => return (None, None, false),
+    };
+
+    let commit = head.target().map(|oid| oid.to_string());
+    let branch = head.shorthand().map(|name| name.to_string());
+    let mut status_opts = StatusOptions::new();
+    status_opts.include_untracked(true);
+    let dirty = repo
+        .statuses(Some(&mut status_opts))
+        .map(|statuses| statuses.len() > 0)
+        .unwrap_or(false);
+    (commit, branch, dirty)
+}
+
+//------------------------------------------------------------------------------
+/// Gets the name of the operator.
+//------------------------------------------------------------------------------
+pub fn get_operator() -> String
+{
+    std::env::var("USER").unwrap_or("unknown".to_string())
+}
+
+//------------------------------------------------------------------------------
+/// Reads the deploy manifest from the remote server.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `remote_path` - Remote path that the manifest is placed under.
+//------------------------------------------------------------------------------
+pub async fn read_manifest
+(
+    fs: &mut Fs,
+    remote_path: &str,
+) -> Option<DeployManifest>
+{
+    let path = remote_path.to_string() + "/" + MANIFEST_FILE;
+    let content = fs.read(&path).await.ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+//------------------------------------------------------------------------------
+/// Writes the deploy manifest to the remote server.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `remote_path` - Remote path that the manifest is placed under.
+/// - `manifest` - Deploy manifest.
+//------------------------------------------------------------------------------
+pub async fn write_manifest
+(
+    fs: &mut Fs,
+    remote_path: &str,
+    manifest: &DeployManifest,
+)
+{
+    let path = remote_path.to_string() + "/" + MANIFEST_FILE;
+    println!("{} : {}", "Writing manifest".green(), &path);
+    let content = serde_json::to_string_pretty(manifest).unwrap();
+    fs.write(&path, content.as_bytes()).await.unwrap();
+}
+
+//------------------------------------------------------------------------------
+/// Shows the deployed revision and compares it with the local HEAD.
+///
+/// # Arguments
+///
+/// - `project` - Project name.
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub async fn status( project: &str, config: &SshConfig )
+{
+    let sftp = get_sftp_session(project).await;
+    let manifest = read_manifest(&mut sftp.fs(), &config.remote_path()).await;
+    sftp.close().await.unwrap();
+
+    let (local_commit, local_branch, dirty) = get_local_head(config);
+    let local_commit = local_commit.unwrap_or("".to_string());
+
+    print_sep();
+    println!("{} : {}", "Project    ".green(), &config.project());
+    println!("{} : {}", "Environment".green(), &config.environment());
+    println!("{} : {}", "Remote path".green(), &config.remote_path());
+    let manifest = match manifest
+    {
+        Some(manifest) => manifest,
+        None =>
+        {
+            println!("No deploy manifest found.");
+            print_sep();
+            return;
+        },
+    };
+
+    let deployed_commit = manifest.commit.clone().unwrap_or("".to_string());
+    println!("{} : {}", "Deployed by".green(), &manifest.operator);
+    println!("{} : {}", "Deployed at".green(), &manifest.timestamp);
+    println!("{} : {}", "Command    ".green(), &manifest.command);
+    println!
+    (
+        "{} : {} ({}){}",
+        "Deployed   ".green(),
+        &deployed_commit,
+        manifest.branch.clone().unwrap_or("".to_string()),
+        if manifest.dirty { " with uncommitted changes" } else { "" },
+    );
+    println!
+    (
+        "{} : {} ({}){}",
+        "Local HEAD ".green(),
+        &local_commit,
+        local_branch.unwrap_or("".to_string()),
+        if dirty { " with uncommitted changes" } else { "" },
+    );
+    println!("{} : {}", "Files      ".green(), manifest.files.len());
+
+    //  Compares the deployed commit with the local HEAD.
+    let state = if deployed_commit == local_commit
+    {
+        "Up to date".green()
+    }
+    else
+    {
+        match get_ahead_behind(config, &local_commit, &deployed_commit)
+        {
+            Some((ahead, behind)) => format!
+            (
+                "Local HEAD is {} commit(s) ahead and {} commit(s) behind",
+                ahead,
+                behind,
+            ).yellow(),
+            None => "Deployed commit is not found locally".red(),
+        }
+    };
+    println!("{} : {}", "Status     ".green(), state);
+    print_sep();
+}
+
+//------------------------------------------------------------------------------
+/// Gets the number of commits that the local commit is ahead and behind the
+/// deployed commit.
+//------------------------------------------------------------------------------
+fn get_ahead_behind
+(
+    config: &SshConfig,
+    local_commit: &str,
+    deployed_commit: &str,
+) -> Option<(usize, usize)>
+{
+    let repo = Repository::open(config.git_path()).ok()?;
+    let local = Oid::from_str(local_commit).ok()?;
+    let deployed = Oid::from_str(deployed_commit).ok()?;
+    repo.graph_ahead_behind(local, deployed).ok()
+}
@@ -0,0 +1,351 @@
+//! File permissions on the remote server.
+
+use crate::util::get_exclude_matcher;
+
+use colored::Colorize;
+use openssh::Session;
+use openssh_sftp_client::metadata::Permissions;
+use schemars::JsonSchema;
+use serde::{ Deserialize, Serialize };
+
+//------------------------------------------------------------------------------
+/// Number of paths passed to a single `chmod` or `chown` command.
+//------------------------------------------------------------------------------
+const PATHS_PER_COMMAND: usize = 100;
+
+//------------------------------------------------------------------------------
+/// Mode, owner and group applied to the remote paths that match the glob.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct PermissionRule
+{
+    /// Glob of the remote paths relative to `remote_path`.
+    path: String,
+
+    /// Mode passed to `chmod`, such as `755` or `u+x`.
+    mode: Option<String>,
+
+    /// Owner passed to `chown`.
+    owner: Option<String>,
+
+    /// Group passed to `chown`.
+    group: Option<String>,
+}
+
+impl PermissionRule
+{
+    //--------------------------------------------------------------------------
+    /// Returns the glob of the paths relative to the remote path.
+    //--------------------------------------------------------------------------
+    pub fn path( &self ) -> String
+    {
+        self.path.clone()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the mode passed to `chmod`.
+    //--------------------------------------------------------------------------
+    pub fn mode( &self ) -> String
+    {
+        self.mode.clone().unwrap_or("".to_string())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the owner passed to `chown`.
+    //--------------------------------------------------------------------------
+    pub fn owner( &self ) -> String
+    {
+        self.owner.clone().unwrap_or("".to_string())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the group passed to `chown`.
+    //--------------------------------------------------------------------------
+    pub fn group( &self ) -> String
+    {
+        self.group.clone().unwrap_or("".to_string())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the argument of `chown`, such as `owner:group`, or an empty
+    /// string if neither the owner nor the group is set.
+    //--------------------------------------------------------------------------
+    fn owner_group( &self ) -> String
+    {
+        match (self.owner().len() > 0, self.group().len() > 0)
+        {
+            (true, true) => self.owner() + ":" + &self.group(),
+            (true, false) => self.owner(),
+            (false, true) => ":".to_string() + &self.group(),
+            (false, false) => "".to_string(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Checks that the glob is valid and the rule can be passed to `chmod` and
+    /// `chown` safely.
+    //--------------------------------------------------------------------------
+    pub fn check( &self ) -> Result<(), String>
+    {
+        if self.path.len() == 0
+        {
+            return Err("path is empty".to_string());
+        }
+        get_exclude_matcher("", &[self.path()])?;
+        check_mode(&self.mode())?;
+        for name in [self.owner(), self.group()]
+        {
+            if name.starts_with('-')
+                || name.contains(|c: char| c.is_whitespace() || c == ':')
+            {
+                return Err(format!("{} is not a valid owner or group", name));
+            }
+        }
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Formats the rule for display.
+    //--------------------------------------------------------------------------
+    fn display( &self ) -> String
+    {
+        let mut changes = Vec::new();
+        if self.mode().len() > 0
+        {
+            changes.push("mode ".to_string() + &self.mode());
+        }
+        if self.owner_group().len() > 0
+        {
+            changes.push("owner ".to_string() + &self.owner_group());
+        }
+        format!("{} => {}", &self.path, changes.join(", "))
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the mode is an octal mode such as `755` or a symbolic mode such
+/// as `u+x,g-w`.
+///
+/// # Arguments
+///
+/// - `mode` - Mode passed to `chmod`. An empty mode is allowed.
+//------------------------------------------------------------------------------
+pub fn check_mode( mode: &str ) -> Result<(), String>
+{
+    let is_octal = mode.len() <= 4
+        && mode.chars().all(|c| ('0'..='7').contains(&c));
+    let is_symbolic = mode.starts_with('-') == false
+        && mode.chars().all(|c| "ugoarwxXst+-=,".contains(c));
+    if is_octal || is_symbolic
+    {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid mode", mode))
+}
+
+//------------------------------------------------------------------------------
+/// Converts the permission bits of a Unix mode into SFTP permissions.
+///
+/// # Arguments
+///
+/// - `mode` - Unix mode, such as `0o755`.
+//------------------------------------------------------------------------------
+pub fn to_permissions( mode: u32 ) -> Permissions
+{
+    let mut permissions = Permissions::new();
+    permissions.set_suid(mode & 0o4000 != 0);
+    permissions.set_sgid(mode & 0o2000 != 0);
+    permissions.set_vtx(mode & 0o1000 != 0);
+    permissions.set_read_by_owner(mode & 0o400 != 0);
+    permissions.set_write_by_owner(mode & 0o200 != 0);
+    permissions.set_execute_by_owner(mode & 0o100 != 0);
+    permissions.set_read_by_group(mode & 0o040 != 0);
+    permissions.set_write_by_group(mode & 0o020 != 0);
+    permissions.set_execute_by_group(mode & 0o010 != 0);
+    permissions.set_read_by_other(mode & 0o004 != 0);
+    permissions.set_write_by_other(mode & 0o002 != 0);
+    permissions.set_execute_by_other(mode & 0o001 != 0);
+    permissions
+}
+
+//------------------------------------------------------------------------------
+/// Prints the permission rules that would be applied in dry run mode.
+///
+/// # Arguments
+///
+/// - `rules` - Permission rules.
+/// - `remote_path` - Remote path that the globs are relative to.
+//------------------------------------------------------------------------------
+pub fn print_permissions_plan( rules: &[PermissionRule], remote_path: &str )
+{
+    for rule in rules
+    {
+        match rule.check()
+        {
+            Ok(_) =>
+            {
+                println!
+                (
+                    "{} : (in {}) {}",
+                    "Permissions".yellow(),
+                    remote_path,
+                    rule.display(),
+                );
+            },
+            Err(e) => println!("{} : {}", "Invalid rule".red(), e),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Applies the permission rules to the remote paths that match their globs.
+/// Rules are applied in order, so later rules win. Returns false if any rule
+/// was invalid or any command failed.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `rules` - Permission rules.
+/// - `remote_path` - Remote path that the globs are relative to.
+//------------------------------------------------------------------------------
+pub async fn apply_permissions
+(
+    session: &Session,
+    rules: &[PermissionRule],
+    remote_path: &str,
+) -> bool
+{
+    if rules.len() == 0
+    {
+        return true;
+    }
+
+    //  Lists the remote paths with their types once for all rules. `-H`
+    //  follows the remote path when it is the symbolic link of the release,
+    //  and only the options that BSD find also supports are used.
+    let listings: [(bool, &[&str]); 2] =
+    [
+        (true, &["-type", "d"]),
+        (false, &["!", "-type", "d"]),
+    ];
+    let mut entries = Vec::new();
+    for (is_dir, filter) in listings
+    {
+        let mut args = vec!["-H", remote_path, "-mindepth", "1"];
+        args.extend_from_slice(filter);
+        match list_paths(session, remote_path, &args).await
+        {
+            Some(paths) =>
+            {
+                entries.extend(paths.into_iter().map(|path| (is_dir, path)));
+            },
+            None => return false,
+        }
+    }
+
+    let mut success = true;
+    for rule in rules
+    {
+        if let Err(e) = rule.check()
+        {
+            println!("{} : {}", "Invalid rule".red(), e);
+            success = false;
+            continue;
+        }
+
+        let matcher = match get_exclude_matcher(remote_path, &[rule.path()])
+        {
+            Ok(matcher) => matcher,
+            Err(e) =>
+            {
+                println!("{} : {}", "Invalid rule".red(), e);
+                success = false;
+                continue;
+            },
+        };
+        let paths: Vec<String> = entries
+            .iter()
+            .filter(|(is_dir, path)| matcher.matched(path, *is_dir).is_ignore())
+            .map(|(_, path)| remote_path.to_string() + "/" + path)
+            .collect();
+        println!
+        (
+            "{} : {} ({} paths)",
+            "Permissions".green(),
+            rule.display(),
+            paths.len(),
+        );
+
+        for chunk in paths.chunks(PATHS_PER_COMMAND)
+        {
+            if rule.mode().len() > 0
+            {
+                success &= run(session, "chmod", &rule.mode(), chunk).await;
+            }
+            if rule.owner_group().len() > 0
+            {
+                success &=
+                    run(session, "chown", &rule.owner_group(), chunk).await;
+            }
+        }
+    }
+    success
+}
+
+//------------------------------------------------------------------------------
+/// Runs `find` with the arguments and returns the paths relative to the remote
+/// path, or None after printing the error if it fails.
+//------------------------------------------------------------------------------
+async fn list_paths
+(
+    session: &Session,
+    remote_path: &str,
+    args: &[&str],
+) -> Option<Vec<String>>
+{
+    let output = session
+        .command("find")
+        .args(args)
+        .output()
+        .await
+        .unwrap();
+    if output.status.success() == false
+    {
+        println!("{}", String::from_utf8_lossy(&output.stderr));
+        return None;
+    }
+    let prefix = remote_path.trim_end_matches('/').to_string() + "/";
+    let paths = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix(&prefix))
+        .map(|path| path.to_string())
+        .collect();
+    Some(paths)
+}
+
+//------------------------------------------------------------------------------
+/// Runs `chmod` or `chown` on the paths and prints the error if it fails.
+//------------------------------------------------------------------------------
+async fn run
+(
+    session: &Session,
+    program: &str,
+    value: &str,
+    paths: &[String],
+) -> bool
+{
+    let output = session
+        .command(program)
+        .arg(value)
+        .args(paths)
+        .output()
+        .await
+        .unwrap();
+    if output.status.success() == false
+    {
+        println!("{}", String::from_utf8_lossy(&output.stderr));
+        return false;
+    }
+    true
+}
@@ -0,0 +1,254 @@
+//! Remote files that `replace` must never delete.
+
+use crate::ssh_config::SshConfig;
+use crate::util::get_exclude_matcher;
+use crate::safety::remove_remote;
+
+use colored::Colorize;
+use futures_util::StreamExt;
+use ignore::overrides::Override;
+use openssh::Session;
+use openssh_sftp_client::fs::Fs;
+use async_recursion::async_recursion;
+
+//------------------------------------------------------------------------------
+/// Paths to be removed and preserved in the remote target directory, relative
+/// to the remote path.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct PreservePlan
+{
+    /// Top-most paths that can be removed.
+    pub removed: Vec<String>,
+
+    /// Top-most paths that match the `preserve` globs.
+    pub preserved: Vec<String>,
+}
+
+impl PreservePlan
+{
+    //--------------------------------------------------------------------------
+    /// Prints the preserved paths.
+    //--------------------------------------------------------------------------
+    pub fn print( &self )
+    {
+        println!("{} : {}", "Preserved  ".green(), self.preserved.len());
+        for path in &self.preserved
+        {
+            println!("    {} {}", "=".cyan(), path);
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the matcher for the `preserve` globs. Returns the error if a glob is
+/// invalid.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub fn get_preserve_matcher( config: &SshConfig ) -> Result<Override, String>
+{
+    get_exclude_matcher(&config.remote_path(), &config.preserve())
+}
+
+//------------------------------------------------------------------------------
+/// Computes which paths in the remote target directory can be removed without
+/// touching the preserved ones. Preserved directories are not walked into.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `base` - Remote path that the returned paths are relative to.
+/// - `target` - Remote target directory.
+/// - `matcher` - Matcher for the `preserve` globs.
+//------------------------------------------------------------------------------
+pub async fn get_preserve_plan
+(
+    fs: &mut Fs,
+    base: &str,
+    target: &str,
+    matcher: &Override,
+) -> PreservePlan
+{
+    let mut plan = PreservePlan::default();
+    if walk(fs, base, target, matcher, &mut plan).await == false
+    {
+        //  Nothing is preserved, so the whole target can be removed.
+        plan.removed = vec![get_relative_path(base, target)];
+    }
+    plan
+}
+
+//------------------------------------------------------------------------------
+/// Walks the remote directory and returns whether it contains any preserved
+/// path. The removable entries are only recorded when it does, since otherwise
+/// the directory itself is removed.
+//------------------------------------------------------------------------------
+#[async_recursion]
+async fn walk
+(
+    fs: &mut Fs,
+    base: &str,
+    dir: &str,
+    matcher: &Override,
+    plan: &mut PreservePlan,
+) -> bool
+{
+    let read_dir = match fs.open_dir(dir).await
+    {
+        Ok(dir) => dir.read_dir(),
+        Err(_) => return false,
+    };
+    let dir_entries: Vec<_> = read_dir.collect().await;
+
+    let mut found = false;
+    let mut removable = Vec::new();
+    for entry in dir_entries.into_iter().flatten()
+    {
+        let filename = entry.filename().to_str().unwrap().to_string();
+        if filename == "." || filename == ".."
+        {
+            continue;
+        }
+
+        let path = dir.to_string() + "/" + &filename;
+        let relative_path = get_relative_path(base, &path);
+        let is_dir = entry
+            .metadata()
+            .file_type()
+            .map(|t| t.is_dir())
+            .unwrap_or(false);
+        if matcher.matched(&relative_path, is_dir).is_ignore()
+        {
+            plan.preserved.push(relative_path);
+            found = true;
+        }
+        else if is_dir && walk(fs, base, &path, matcher, plan).await
+        {
+            found = true;
+        }
+        else
+        {
+            removable.push(relative_path);
+        }
+    }
+
+    if found
+    {
+        plan.removed.extend(removable);
+    }
+    found
+}
+
+//------------------------------------------------------------------------------
+/// Gets the path relative to the base path.
+//------------------------------------------------------------------------------
+fn get_relative_path( base: &str, path: &str ) -> String
+{
+    path.replacen(base, "", 1).trim_start_matches("/").to_string()
+}
+
+//------------------------------------------------------------------------------
+/// Joins the relative path to the base path.
+//------------------------------------------------------------------------------
+pub fn join_path( base: &str, relative_path: &str ) -> String
+{
+    if relative_path.len() == 0
+    {
+        return base.to_string();
+    }
+    base.to_string() + "/" + relative_path
+}
+
+//------------------------------------------------------------------------------
+/// Gets the parent directory of the remote path.
+//------------------------------------------------------------------------------
+pub fn get_parent_path( path: &str ) -> String
+{
+    match path.rfind('/')
+    {
+        Some(pos) => path[..pos].to_string(),
+        None => ".".to_string(),
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Removes the paths in the plan under the remote path.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+/// - `remote_path` - Remote path that the paths in the plan are relative to.
+/// - `plan` - Preserve plan.
+//------------------------------------------------------------------------------
+pub async fn remove_unpreserved
+(
+    session: &Session,
+    config: &SshConfig,
+    remote_path: &str,
+    plan: &PreservePlan,
+)
+{
+    for relative_path in &plan.removed
+    {
+        let path = join_path(remote_path, relative_path);
+        remove_remote(session, config, &path).await;
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Copies the preserved paths from the current release into the new release.
+/// Returns false after printing the error if a path could not be copied.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `current` - Path to the current release.
+/// - `release` - Path to the new release.
+/// - `plan` - Preserve plan.
+//------------------------------------------------------------------------------
+pub async fn copy_preserved
+(
+    session: &Session,
+    current: &str,
+    release: &str,
+    plan: &PreservePlan,
+) -> bool
+{
+    for relative_path in &plan.preserved
+    {
+        let from = join_path(current, relative_path);
+        let to = join_path(release, relative_path);
+        let parent = get_parent_path(&to);
+        let commands =
+        [
+            ("mkdir", vec!["-p", parent.as_str()]),
+            ("cp", vec!["-a", from.as_str(), to.as_str()]),
+        ];
+        for (program, args) in commands
+        {
+            let output = session
+                .command(program)
+                .args(args)
+                .output()
+                .await
+                .unwrap();
+            if output.status.success() == false
+            {
+                println!
+                (
+                    "{} : Failed to copy {} into {}: {}",
+                    "Error".red(),
+                    &from,
+                    &to,
+                    String::from_utf8_lossy(&output.stderr).trim(),
+                );
+                return false;
+            }
+        }
+    }
+    true
+}
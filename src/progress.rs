@@ -0,0 +1,222 @@
+//! Progress reporting of file transfers.
+
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+
+use colored::Colorize;
+use indicatif::{
+    HumanBytes,
+    HumanDuration,
+    ProgressBar,
+    ProgressDrawTarget,
+    ProgressStyle,
+};
+
+//------------------------------------------------------------------------------
+/// Interval of the log lines when stdout is not a terminal.
+//------------------------------------------------------------------------------
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+//------------------------------------------------------------------------------
+/// Counters of the transfer.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+struct State
+{
+    files: u64,
+    bytes: u64,
+    last_log: Instant,
+}
+
+//------------------------------------------------------------------------------
+/// Progress of a transfer, shown as a progress bar on a terminal, or as
+/// periodic log lines otherwise.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct Progress
+{
+    label: String,
+    total_files: u64,
+    total_bytes: u64,
+    started: Instant,
+    bar: Option<ProgressBar>,
+    state: Mutex<State>,
+}
+
+impl Progress
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new progress.
+    ///
+    /// # Arguments
+    ///
+    /// - `label` - Label of the transfer, such as `Uploading`.
+    /// - `total_files` - Number of files to transfer.
+    /// - `total_bytes` - Number of bytes to transfer, or 0 if unknown.
+    //--------------------------------------------------------------------------
+    pub fn new( label: &str, total_files: u64, total_bytes: u64 ) -> Self
+    {
+        let bar = if std::io::stdout().is_terminal()
+        {
+            let template = if total_bytes > 0
+            {
+                "{msg} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} \
+                 ETA {eta}"
+            }
+            else
+            {
+                "{spinner} {msg} {bytes} {bytes_per_sec} {elapsed}"
+            };
+            let bar = ProgressBar::with_draw_target
+            (
+                Some(total_bytes),
+                ProgressDrawTarget::stdout(),
+            );
+            bar.set_style
+            (
+                ProgressStyle::with_template(template)
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar.enable_steady_tick(Duration::from_millis(200));
+            Some(bar)
+        }
+        else
+        {
+            None
+        };
+
+        let progress = Self
+        {
+            label: label.to_string(),
+            total_files,
+            total_bytes,
+            started: Instant::now(),
+            bar,
+            state: Mutex::new(State
+            {
+                files: 0,
+                bytes: 0,
+                last_log: Instant::now(),
+            }),
+        };
+        progress.update_message(0);
+        progress
+    }
+
+    //--------------------------------------------------------------------------
+    /// Prints the message without breaking the progress bar.
+    //--------------------------------------------------------------------------
+    pub fn println( &self, message: &str )
+    {
+        match &self.bar
+        {
+            Some(bar) => bar.println(message),
+            None => println!("{}", message),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds the number of transferred bytes.
+    //--------------------------------------------------------------------------
+    pub fn inc_bytes( &self, bytes: u64 )
+    {
+        let mut state = self.state.lock().unwrap();
+        state.bytes += bytes;
+        match &self.bar
+        {
+            Some(bar) => bar.inc(bytes),
+            None =>
+            {
+                if state.last_log.elapsed() >= LOG_INTERVAL
+                {
+                    state.last_log = Instant::now();
+                    println!("{}", self.format_log(&state));
+                }
+            },
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Counts a transferred file.
+    //--------------------------------------------------------------------------
+    pub fn inc_file( &self )
+    {
+        let files =
+        {
+            let mut state = self.state.lock().unwrap();
+            state.files += 1;
+            state.files
+        };
+        self.update_message(files);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Finishes the progress and prints the summary.
+    //--------------------------------------------------------------------------
+    pub fn finish( &self )
+    {
+        let state = self.state.lock().unwrap();
+        if let Some(bar) = &self.bar
+        {
+            bar.finish_and_clear();
+        }
+        println!("{}", self.format_log(&state));
+    }
+
+    //--------------------------------------------------------------------------
+    /// Updates the message of the progress bar.
+    //--------------------------------------------------------------------------
+    fn update_message( &self, files: u64 )
+    {
+        if let Some(bar) = &self.bar
+        {
+            if self.total_files > 0
+            {
+                bar.set_message(format!
+                (
+                    "{} {}/{} files",
+                    &self.label,
+                    files,
+                    self.total_files,
+                ));
+            }
+            else
+            {
+                bar.set_message(self.label.clone());
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Formats the log line of the progress.
+    //--------------------------------------------------------------------------
+    fn format_log( &self, state: &State ) -> String
+    {
+        let elapsed = self.started.elapsed().as_secs_f64().max(0.001);
+        let rate = (state.bytes as f64 / elapsed) as u64;
+
+        let mut log = format!("{} :", &self.label.green());
+        if self.total_files > 0
+        {
+            log += &format!(" {}/{} files,", state.files, self.total_files);
+        }
+        log += &format!(" {}", HumanBytes(state.bytes));
+        if self.total_bytes > 0
+        {
+            log += &format!("/{}", HumanBytes(self.total_bytes));
+        }
+        log += &format!(", {}/s", HumanBytes(rate));
+        if self.total_bytes > state.bytes && rate > 0
+        {
+            let eta = (self.total_bytes - state.bytes) / rate;
+            log += &format!
+            (
+                ", ETA {}",
+                HumanDuration(Duration::from_secs(eta)),
+            );
+        }
+        log
+    }
+}
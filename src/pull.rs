@@ -0,0 +1,275 @@
+//! Specific implementation of operations related to downloading remote files
+//! into the local repository.
+
+use crate::ssh_config::SshConfig;
+use crate::sync::get_remote_entries;
+use crate::manifest::MANIFEST_FILE;
+use crate::transfer::download_file;
+use crate::progress::Progress;
+use crate::util::{
+    get_sftp_session,
+    print_sep,
+    print_dry_run_transfer,
+    confirm,
+};
+
+use std::collections::BTreeMap;
+use std::path::{ Component, Path };
+
+use colored::Colorize;
+use openssh_sftp_client::fs::Fs;
+use openssh_sftp_client::metadata::MetaData;
+
+//------------------------------------------------------------------------------
+/// Downloads the specified remote files or directories into the matching
+/// place in the local source directory.
+///
+/// # Arguments
+///
+/// - `project` - Project name.
+/// - `config` - SSH configuration.
+/// - `paths` - Relative paths from the remote path to download.
+/// - `dry_run` - Whether to only print what would be done.
+//------------------------------------------------------------------------------
+pub async fn pull
+(
+    project: &str,
+    config: &SshConfig,
+    paths: &[String],
+    dry_run: bool,
+)
+{
+    let remote_path = config.remote_path();
+    let git_src_path = config.git_src_path();
+
+    //  Expands directories into the files under them. Symbolic links are
+    //  followed only if they point inside the remote path.
+    let sftp = get_sftp_session(project).await;
+    let mut fs = sftp.fs();
+    let real_remote_path = match fs.canonicalize(&remote_path).await
+    {
+        Ok(path) => path,
+        Err(_) =>
+        {
+            println!("{} : {}", "Not found".red(), &remote_path);
+            drop(fs);
+            sftp.close().await.unwrap();
+            return;
+        },
+    };
+    let mut files = Vec::new();
+    let mut total_bytes = 0;
+    for path in paths
+    {
+        let path = path.trim_matches('/');
+        if Path::new(path).components().any(|c| c == Component::ParentDir)
+        {
+            println!
+            (
+                "{} : {} must not contain `..`.",
+                "Error".red(),
+                path,
+            );
+            continue;
+        }
+
+        let remote_file_path = remote_path.clone() + "/" + path;
+        let metadata = match get_inside_metadata
+        (
+            &mut fs,
+            &real_remote_path,
+            &remote_file_path,
+        ).await
+        {
+            Some(metadata) => metadata,
+            None => continue,
+        };
+
+        if metadata.file_type().map(|t| t.is_dir()).unwrap_or(false)
+        {
+            let mut entries = BTreeMap::new();
+            get_remote_entries
+            (
+                &mut fs,
+                &remote_path,
+                &remote_file_path,
+                &mut entries,
+            ).await;
+            for (relative_path, mut metadata) in entries
+            {
+                if relative_path == MANIFEST_FILE
+                {
+                    continue;
+                }
+                if metadata.file_type().map(|t| t.is_symlink()).unwrap_or(false)
+                {
+                    let entry_path = remote_path.clone() + "/" + &relative_path;
+                    metadata = match get_inside_metadata
+                    (
+                        &mut fs,
+                        &real_remote_path,
+                        &entry_path,
+                    ).await
+                    {
+                        Some(metadata) => metadata,
+                        None => continue,
+                    };
+                }
+                if metadata.file_type().map(|t| t.is_file()).unwrap_or(false)
+                {
+                    files.push(relative_path);
+                    total_bytes += metadata.len().unwrap_or(0);
+                }
+            }
+        }
+        else
+        {
+            files.push(path.to_string());
+            total_bytes += metadata.len().unwrap_or(0);
+        }
+    }
+
+    if files.len() == 0
+    {
+        println!("No files to download.");
+        drop(fs);
+        sftp.close().await.unwrap();
+        return;
+    }
+
+    print_sep();
+    println!("{} : {}", "Project    ".green(), &config.project());
+    println!("{} : {}", "Environment".green(), &config.environment());
+    println!("{} : {}", "Remote path".green(), &remote_path);
+    println!("{} : {}", "Local path ".green(), &git_src_path);
+    println!("{} :",    "Files      ".green());
+    for relative_path in &files
+    {
+        let local_path = git_src_path.clone() + "/" + relative_path;
+        let mark = if Path::new(&local_path).exists() { "~" } else { "+" };
+        println!("    {} {}", mark, relative_path);
+    }
+    print_sep();
+
+    if dry_run
+    {
+        println!("{}", "Dry run. No changes will be made.".yellow());
+        for relative_path in &files
+        {
+            print_dry_run_transfer
+            (
+                "Download",
+                &(remote_path.clone() + "/" + relative_path),
+                &(git_src_path.clone() + "/" + relative_path),
+            );
+        }
+        drop(fs);
+        sftp.close().await.unwrap();
+        return;
+    }
+
+    if confirm("Are you sure you want to overwrite the local files?") == false
+    {
+        println!("Canceled.");
+        drop(fs);
+        sftp.close().await.unwrap();
+        return;
+    }
+
+    //  Downloads all files. A file that fails is reported and skipped.
+    let total_files = files.len() as u64;
+    let progress = Progress::new("Downloading", total_files, total_bytes);
+    let mut failed = 0;
+    for relative_path in &files
+    {
+        let from = remote_path.clone() + "/" + relative_path;
+        let to = git_src_path.clone() + "/" + relative_path;
+        progress.println
+        (
+            &format!("{} : {} => {}", "Downloading".green(), &from, &to),
+        );
+
+        let result = match std::fs::create_dir_all
+        (
+            Path::new(&to).parent().unwrap(),
+        )
+        {
+            Ok(_) =>
+            {
+                download_file(&sftp.options(), &from, Path::new(&to), &progress)
+                    .await
+            },
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result
+        {
+            progress.println(&format!
+            (
+                "{} : Failed to download {}: {}",
+                "Error".red(),
+                &from,
+                e,
+            ));
+            failed += 1;
+        }
+        progress.inc_file();
+    }
+    progress.finish();
+    drop(fs);
+    sftp.close().await.unwrap();
+    if failed > 0
+    {
+        println!("{} of {} file(s) failed.", failed, total_files);
+        return;
+    }
+    println!("Done.");
+}
+
+//------------------------------------------------------------------------------
+/// Gets the metadata of the remote path, following symbolic links. Returns
+/// None after printing the reason if the path is not found or resolves to a
+/// place outside the remote path.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `real_remote_path` - Remote path with symbolic links resolved.
+/// - `path` - Remote path to check.
+//------------------------------------------------------------------------------
+async fn get_inside_metadata
+(
+    fs: &mut Fs,
+    real_remote_path: &Path,
+    path: &str,
+) -> Option<MetaData>
+{
+    let real_path = match fs.canonicalize(path).await
+    {
+        Ok(real_path) => real_path,
+        Err(_) =>
+        {
+            println!("{} : {}", "Not found".red(), path);
+            return None;
+        },
+    };
+    if real_path.starts_with(real_remote_path) == false
+    {
+        println!
+        (
+            "{} : {} points outside the remote path ({}).",
+            "Skip".yellow(),
+            path,
+            real_path.display(),
+        );
+        return None;
+    }
+    match fs.metadata(path).await
+    {
+        Ok(metadata) => Some(metadata),
+        Err(_) =>
+        {
+            println!("{} : {}", "Not found".red(), path);
+            None
+        },
+    }
+}
@@ -0,0 +1,394 @@
+//! Specific implementation of operations related to release directories.
+
+use crate::ssh_config::SshConfig;
+use crate::upload::{ clear_cache, print_clear_cache_plan };
+use crate::lock::{ acquire_lock, release_lock };
+use crate::safety::remove_remote;
+use crate::util::{
+    get_session,
+    shell_quote,
+    print_dry_run_command,
+    print_sep,
+    confirm,
+    get_current_time_for_filename,
+};
+
+use colored::Colorize;
+use openssh::Session;
+
+//------------------------------------------------------------------------------
+/// Returns the path to a new release directory.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub fn new_release_path( config: &SshConfig ) -> String
+{
+    config.releases_path() + "/" + &get_current_time_for_filename()
+}
+
+//------------------------------------------------------------------------------
+/// Gets the release directories on the remote server sorted from oldest to
+/// newest.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub async fn get_releases
+(
+    session: &Session,
+    config: &SshConfig,
+) -> Vec<String>
+{
+    let releases_path = config.releases_path();
+    let output = session
+        .command("ls")
+        .args(["-1", &releases_path])
+        .output()
+        .await
+        .unwrap();
+
+    let mut releases: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.len() > 0)
+        .map(|line| releases_path.clone() + "/" + line)
+        .collect();
+    releases.sort();
+    releases
+}
+
+//------------------------------------------------------------------------------
+/// Gets the release directory that the remote path currently points to.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub async fn get_current_release
+(
+    session: &Session,
+    config: &SshConfig,
+) -> Option<String>
+{
+    let output = session
+        .command("readlink")
+        .args(["-f", &config.remote_path()])
+        .output()
+        .await
+        .unwrap();
+    if output.status.success() == false
+    {
+        return None;
+    }
+
+    let current = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if current.len() > 0
+    {
+        Some(current)
+    }
+    else
+    {
+        None
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the remote path can be switched to a release: it must be a
+/// symbolic link or not exist yet. An existing directory is not replaced, so
+/// that a new release is not uploaded only to fail at the switch.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub async fn check_release_target
+(
+    session: &Session,
+    config: &SshConfig,
+) -> bool
+{
+    let remote_path = config.remote_path();
+    let command = format!
+    (
+        "test -L {0} || ! test -e {0}",
+        shell_quote(&remote_path),
+    );
+    let output = session.shell(&command).output().await.unwrap();
+    if output.status.success() == false
+    {
+        let releases_path = config.releases_path();
+        println!
+        (
+            "{} : {} is not a symbolic link. Move it into {} and link it \
+            before deploying in release mode, for example:",
+            "Error".red(),
+            &remote_path,
+            &releases_path,
+        );
+        println!
+        (
+            "    mkdir -p {1} && mv {0} {1}/initial && ln -s {1}/initial {0}",
+            &remote_path,
+            &releases_path,
+        );
+        return false;
+    }
+    true
+}
+
+//------------------------------------------------------------------------------
+/// Returns the path to the temporary link that is renamed over the remote
+/// path.
+//------------------------------------------------------------------------------
+fn get_temp_link_path( remote_path: &str ) -> String
+{
+    remote_path.to_string() + ".resm_tmp"
+}
+
+//------------------------------------------------------------------------------
+/// Switches the symbolic link of the remote path to the specified release.
+///
+/// The link is created under a temporary name and then renamed over the remote
+/// path with `mv -T` so that the switch is atomic. Where `mv -T` is not
+/// supported, the link is replaced with `ln -sfn` instead.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+/// - `release_path` - Path to the release directory.
+//------------------------------------------------------------------------------
+pub async fn switch_release
+(
+    session: &Session,
+    config: &SshConfig,
+    release_path: &str,
+) -> bool
+{
+    let remote_path = config.remote_path();
+    let temp_link = get_temp_link_path(&remote_path);
+
+    let output = session
+        .command("ln")
+        .args(["-sfn", release_path, &temp_link])
+        .output()
+        .await
+        .unwrap();
+    if output.status.success() == false
+    {
+        println!("{}", String::from_utf8_lossy(&output.stderr));
+        return false;
+    }
+
+    //  `mv -T` replaces the link atomically but is GNU-only. Where it is not
+    //  supported, such as on BSD and macOS, the link is replaced with
+    //  `ln -sfn`, which is not atomic.
+    let output = session
+        .command("mv")
+        .args(["-Tf", &temp_link, &remote_path])
+        .output()
+        .await
+        .unwrap();
+    if output.status.success() == false
+    {
+        session
+            .command("rm")
+            .args(["-f", &temp_link])
+            .output()
+            .await
+            .unwrap();
+        let output = session
+            .command("ln")
+            .args(["-sfn", release_path, &remote_path])
+            .output()
+            .await
+            .unwrap();
+        if output.status.success() == false
+        {
+            println!("{}", String::from_utf8_lossy(&output.stderr));
+            return false;
+        }
+        println!
+        (
+            "{} : mv -T is not supported, so {} was replaced non-atomically.",
+            "Warning".yellow(),
+            &remote_path,
+        );
+    }
+
+    println!
+    (
+        "{} : {} => {}",
+        "Switched".green(),
+        &remote_path,
+        release_path,
+    );
+    true
+}
+
+//------------------------------------------------------------------------------
+/// Removes old releases so that only the configured number of releases are
+/// kept. The current release is never removed.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+//------------------------------------------------------------------------------
+pub async fn prune_releases( session: &Session, config: &SshConfig )
+{
+    let releases = get_releases(session, config).await;
+    let keep = config.keep_releases();
+    if releases.len() <= keep
+    {
+        return;
+    }
+
+    //  Without knowing the current release, nothing is removed, since it
+    //  could be one of the old ones.
+    let current = match get_current_release(session, config).await
+    {
+        Some(current) if releases.contains(&current) => current,
+        _ =>
+        {
+            println!
+            (
+                "{} : The current release is not in {}. Old releases are \
+                not removed.",
+                "Warning".yellow(),
+                &config.releases_path(),
+            );
+            return;
+        },
+    };
+    for release in &releases[..releases.len() - keep]
+    {
+        if *release == current
+        {
+            continue;
+        }
+
+        println!("{} : {}", "Removing old release".green(), release);
+        remove_remote(session, config, release).await;
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Moves the symbolic link of the remote path back to the previous release.
+///
+/// # Arguments
+///
+/// - `project` - Project name.
+/// - `config` - SSH configuration.
+/// - `dry_run` - Whether to only print what would be done.
+//------------------------------------------------------------------------------
+pub async fn rollback( project: &str, config: &SshConfig, dry_run: bool )
+{
+    if config.release_mode() == false
+    {
+        println!("Release mode is not enabled for {}.", project);
+        return;
+    }
+
+    let session = get_session(project).await;
+    let releases = get_releases(&session, config).await;
+    let current = get_current_release(&session, config).await;
+
+    //  Finds the release before the current one. The current release must be
+    //  one of the releases, otherwise the previous one is unknown.
+    let previous = match &current
+    {
+        Some(current) if releases.contains(current) =>
+        {
+            releases
+                .iter()
+                .take_while(|release| *release != current)
+                .last()
+                .cloned()
+        },
+        _ =>
+        {
+            println!
+            (
+                "{} : {} does not point to a release in {}.",
+                "Error".red(),
+                &config.remote_path(),
+                &config.releases_path(),
+            );
+            session.close().await.unwrap();
+            return;
+        },
+    };
+
+    print_sep();
+    println!("{} : {}", "Project    ".green(), &config.project());
+    println!("{} : {}", "Environment".green(), &config.environment());
+    println!("{} : {}", "Remote path".green(), &config.remote_path());
+    println!
+    (
+        "{} : {}",
+        "Current    ".green(),
+        current.clone().unwrap_or("".to_string()),
+    );
+    println!
+    (
+        "{} : {}",
+        "Previous   ".green(),
+        previous.clone().unwrap_or("".to_string()),
+    );
+    print_sep();
+
+    let previous = match previous
+    {
+        Some(previous) => previous,
+        None =>
+        {
+            println!("No previous release found.");
+            session.close().await.unwrap();
+            return;
+        },
+    };
+
+    if dry_run
+    {
+        let remote_path = config.remote_path();
+        let temp_link = get_temp_link_path(&remote_path);
+        println!("{}", "Dry run. No changes will be made.".yellow());
+        print_dry_run_command("ln", &["-sfn", &previous, &temp_link]);
+        print_dry_run_command("mv", &["-Tf", &temp_link, &remote_path]);
+        print_clear_cache_plan(config);
+        session.close().await.unwrap();
+        return;
+    }
+
+    if confirm("Are you sure you want to roll back to the previous release?")
+        == false
+    {
+        println!("Canceled.");
+        session.close().await.unwrap();
+        return;
+    }
+
+    if acquire_lock(&session, config, "rollback").await == false
+    {
+        session.close().await.unwrap();
+        return;
+    }
+
+    if switch_release(&session, config, &previous).await == false
+    {
+        println!("Failed to switch the release.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
+    clear_cache(&session, config).await;
+    release_lock(&session, config).await;
+    session.close().await.unwrap();
+    println!("Done.");
+}
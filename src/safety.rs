@@ -0,0 +1,316 @@
+//! Validation of remote paths before running destructive commands.
+
+use crate::ssh_config::SshConfig;
+use crate::archive::get_staging_path;
+
+use colored::Colorize;
+use openssh::Session;
+
+//------------------------------------------------------------------------------
+/// Checks that the remote path is safe to operate on: it must be an absolute
+/// path without `..`, and must not be the root or a home directory.
+///
+/// # Arguments
+///
+/// - `path` - Remote path to check.
+//------------------------------------------------------------------------------
+pub fn check_remote_path( path: &str ) -> Result<(), String>
+{
+    if path.len() == 0
+    {
+        return Err("path is empty or /".to_string());
+    }
+    if path.starts_with('/') == false
+    {
+        return Err(format!("{} is not an absolute path", path));
+    }
+    if path.split('/').any(|component| component == "..")
+    {
+        return Err(format!("{} contains '..'", path));
+    }
+
+    let components: Vec<&str> = path
+        .split('/')
+        .filter(|component| component.len() > 0 && *component != ".")
+        .collect();
+    match components.as_slice()
+    {
+        [] => Err(format!("{} is the root directory", path)),
+        ["root"] | ["home"] | ["home", _] | ["Users"] | ["Users", _] =>
+        {
+            Err(format!("{} is a home directory", path))
+        },
+        _ => Ok(()),
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the remote path can be removed. In addition to
+/// `check_remote_path`, the path must be inside the remote path, the staging
+/// directory of the archive next to it, or the releases directory in release
+/// mode.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+/// - `path` - Remote path to be removed.
+//------------------------------------------------------------------------------
+pub fn check_removal( config: &SshConfig, path: &str ) -> Result<(), String>
+{
+    let remote_path = config.remote_path();
+    check_remote_path(&remote_path)
+        .map_err(|e| "remote_path is invalid: ".to_string() + &e)?;
+    check_remote_path(path)?;
+
+    let path = path.trim_end_matches('/');
+    if is_within(path, &remote_path)
+        || is_within(path, &get_staging_path(&remote_path))
+        || (config.release_mode() && is_inside(path, &config.releases_path()))
+    {
+        return Ok(());
+    }
+    Err(format!("{} is outside remote_path ({})", path, &remote_path))
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the remote path is a temporary backup file, which is a zip file
+/// directly in `/tmp`, so that nothing else in `/tmp` can be removed.
+///
+/// # Arguments
+///
+/// - `path` - Remote path to be removed.
+//------------------------------------------------------------------------------
+pub fn check_temp_file_removal( path: &str ) -> Result<(), String>
+{
+    check_remote_path(path)?;
+    match path.strip_prefix("/tmp/")
+    {
+        Some(name) if name.contains('/') == false
+            && name.starts_with('.') == false
+            && name.len() > ".zip".len()
+            && name.ends_with(".zip") =>
+        {
+            Ok(())
+        },
+        _ => Err(format!("{} is not a temporary backup file", path)),
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Returns whether the path is the root path or under it.
+//------------------------------------------------------------------------------
+fn is_within( path: &str, root: &str ) -> bool
+{
+    path == root || is_inside(path, root)
+}
+
+//------------------------------------------------------------------------------
+/// Returns whether the path is under the root path.
+//------------------------------------------------------------------------------
+fn is_inside( path: &str, root: &str ) -> bool
+{
+    path.starts_with(&(root.to_string() + "/"))
+}
+
+//------------------------------------------------------------------------------
+/// Prints the reason why the operation was refused for the project.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+/// - `message` - Reason.
+//------------------------------------------------------------------------------
+pub fn print_refused( config: &SshConfig, message: &str )
+{
+    println!
+    (
+        "{} : Refused to operate on {} ({}): {}",
+        "Error".red(),
+        &config.project(),
+        &config.environment(),
+        message,
+    );
+}
+
+//------------------------------------------------------------------------------
+/// Removes the remote path recursively after checking that it is safe to
+/// remove. Returns false if the removal was refused.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+/// - `path` - Remote path to be removed.
+//------------------------------------------------------------------------------
+pub async fn remove_remote
+(
+    session: &Session,
+    config: &SshConfig,
+    path: &str,
+) -> bool
+{
+    if let Err(e) = check_removal(config, path)
+    {
+        print_refused(config, &e);
+        return false;
+    }
+
+    session
+        .command("rm")
+        .args(["-r", path])
+        .output()
+        .await
+        .unwrap();
+    true
+}
+
+//------------------------------------------------------------------------------
+/// Removes the temporary backup file after checking it with
+/// `check_temp_file_removal`. Returns false if the removal was refused.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+/// - `path` - Remote path to the temporary backup file.
+//------------------------------------------------------------------------------
+pub async fn remove_temp_file
+(
+    session: &Session,
+    config: &SshConfig,
+    path: &str,
+) -> bool
+{
+    if let Err(e) = check_temp_file_removal(path)
+    {
+        print_refused(config, &e);
+        return false;
+    }
+
+    session
+        .command("rm")
+        .args(["-f", path])
+        .output()
+        .await
+        .unwrap();
+    true
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    use serde_json::json;
+
+    //--------------------------------------------------------------------------
+    /// Creates the configuration whose remote path is `/var/www/app`.
+    //--------------------------------------------------------------------------
+    fn config( release_mode: bool ) -> SshConfig
+    {
+        serde_json::from_value(json!(
+        {
+            "project": "app",
+            "environment": "prod",
+            "remote_path": "/var/www/app",
+            "release_mode": release_mode,
+            "connect_info": { "host": "example.com" },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn check_remote_path_rejects_parent_components()
+    {
+        assert!(check_remote_path("/var/www/../app").is_err());
+        assert!(check_remote_path("/var/www/app/..").is_err());
+        assert!(check_remote_path("/var/www/..app").is_ok());
+    }
+
+    #[test]
+    fn check_remote_path_handles_trailing_slashes()
+    {
+        assert!(check_remote_path("/").is_err());
+        assert!(check_remote_path("//").is_err());
+        assert!(check_remote_path("/var/www/app/").is_ok());
+        assert!(check_remote_path("/home/x/").is_err());
+    }
+
+    #[test]
+    fn check_remote_path_rejects_home_directories()
+    {
+        assert!(check_remote_path("/home").is_err());
+        assert!(check_remote_path("/home/x").is_err());
+        assert!(check_remote_path("/home/./x").is_err());
+        assert!(check_remote_path("/root").is_err());
+        assert!(check_remote_path("/Users/x").is_err());
+        assert!(check_remote_path("/home/x/app").is_ok());
+    }
+
+    #[test]
+    fn check_remote_path_rejects_relative_paths()
+    {
+        assert!(check_remote_path("").is_err());
+        assert!(check_remote_path("var/www/app").is_err());
+    }
+
+    #[test]
+    fn check_removal_accepts_the_remote_path_and_under()
+    {
+        let config = config(false);
+        assert!(check_removal(&config, "/var/www/app").is_ok());
+        assert!(check_removal(&config, "/var/www/app/").is_ok());
+        assert!(check_removal(&config, "/var/www/app/public").is_ok());
+        assert!(check_removal(&config, "/var/www/app.resm-staging").is_ok());
+    }
+
+    #[test]
+    fn check_removal_rejects_siblings_sharing_the_prefix()
+    {
+        let config = config(false);
+        assert!(check_removal(&config, "/var/www/app2").is_err());
+        assert!(check_removal(&config, "/var/www/app2/public").is_err());
+        assert!(check_removal(&config, "/var/www").is_err());
+    }
+
+    #[test]
+    fn check_removal_rejects_parent_components()
+    {
+        let config = config(false);
+        assert!(check_removal(&config, "/var/www/app/../app2").is_err());
+        assert!(check_removal(&config, "/var/www/app/..").is_err());
+    }
+
+    #[test]
+    fn check_removal_rejects_home_and_tmp()
+    {
+        let config = config(false);
+        assert!(check_removal(&config, "/home/x").is_err());
+        assert!(check_removal(&config, "/tmp/app_20240101.zip").is_err());
+        assert!(check_removal(&config, "/tmp").is_err());
+    }
+
+    #[test]
+    fn check_removal_accepts_releases_only_in_release_mode()
+    {
+        let release = "/var/www/app.releases/20240101000000";
+        assert!(check_removal(&config(true), release).is_ok());
+        assert!(check_removal(&config(false), release).is_err());
+        let config = config(true);
+        assert!(check_removal(&config, "/var/www/app.releases").is_err());
+        assert!(check_removal(&config, "/var/www/app.releases2/x").is_err());
+    }
+
+    #[test]
+    fn check_temp_file_removal_accepts_only_backup_files()
+    {
+        assert!(check_temp_file_removal("/tmp/app_20240101.zip").is_ok());
+        assert!(check_temp_file_removal("/tmp").is_err());
+        assert!(check_temp_file_removal("/tmp/").is_err());
+        assert!(check_temp_file_removal("/tmp/.zip").is_err());
+        assert!(check_temp_file_removal("/tmp/dir/app.zip").is_err());
+        assert!(check_temp_file_removal("/tmp/../etc/app.zip").is_err());
+        assert!(check_temp_file_removal("/tmp/app.tar.gz").is_err());
+        assert!(check_temp_file_removal("/var/tmp/app.zip").is_err());
+    }
+}
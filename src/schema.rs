@@ -0,0 +1,74 @@
+//! JSON Schema of the configuration files.
+
+use crate::ssh_config::SshConfig;
+
+use schemars::schema_for;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use serde_json::json;
+
+//------------------------------------------------------------------------------
+/// Prints the JSON Schema of the JSON configuration files, which is generated
+/// from the configuration types.
+///
+/// Only `project` and `environment` are required in an entry, since the other
+/// fields, including the fields of `connect_info`, can be inherited through
+/// `extends`.
+//------------------------------------------------------------------------------
+pub fn print_schema()
+{
+    let mut schema = schema_for!(Vec<SshConfig>);
+    schema.schema.metadata().title = Some("resm configuration".to_string());
+
+    let definitions = &mut schema.definitions;
+    if let Some(Schema::Object(mut connect_info)) =
+        definitions.get("ConnectInfo").cloned()
+    {
+        connect_info.object().required.clear();
+        definitions.insert
+        (
+            "InheritedConnectInfo".to_string(),
+            Schema::Object(connect_info),
+        );
+    }
+    if let Some(Schema::Object(entry)) = definitions.get_mut("SshConfig")
+    {
+        let object = entry.object();
+        object.required
+            .retain(|field| field == "project" || field == "environment");
+        if let Some(Schema::Object(connect_info)) =
+            object.properties.get_mut("connect_info")
+        {
+            connect_info.subschemas().all_of = Some(vec![Schema::new_ref
+            (
+                "#/definitions/InheritedConnectInfo".to_string(),
+            )]);
+        }
+    }
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
+
+//------------------------------------------------------------------------------
+/// Gets the schema of a secret, which is a string or a secret reference
+/// resolved when the files are loaded.
+//------------------------------------------------------------------------------
+pub fn secret_schema( _gen: &mut SchemaGenerator ) -> Schema
+{
+    let reference = |kind: &str| json!(
+    {
+        "type": "object",
+        "properties": { kind: { "type": "string" } },
+        "required": [kind],
+        "additionalProperties": false,
+    });
+    serde_json::from_value(json!(
+    {
+        "anyOf":
+        [
+            { "type": ["string", "null"] },
+            reference("from_file"),
+            reference("from_command"),
+        ],
+    }))
+    .unwrap()
+}
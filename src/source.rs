@@ -0,0 +1,375 @@
+//! Local files to be uploaded to the remote server.
+
+use crate::ssh_config::SshConfig;
+use crate::transfer::{ get_file_hash, get_reader_hash };
+use crate::util::{ get_file_paths, get_exclude_matcher, is_excluded };
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ Cursor, Read, Seek, SeekFrom, Write };
+use std::os::unix::fs::PermissionsExt;
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+
+use git2::{ ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult };
+use ignore::gitignore::GitignoreBuilder;
+
+//------------------------------------------------------------------------------
+/// Size of the largest blob that is read into memory. Larger blobs are copied
+/// into a temporary file.
+//------------------------------------------------------------------------------
+const MAX_MEMORY_BLOB_SIZE: u64 = 1024 * 1024;
+
+//------------------------------------------------------------------------------
+/// File to be uploaded, either on disk or in a git commit with its mode.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub enum SourceFile
+{
+    Path(PathBuf),
+    Blob(GitBlob),
+}
+
+//------------------------------------------------------------------------------
+/// Blob in a git repository. Only the size is read when it is collected, and
+/// the content is read when the file is opened.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct GitBlob
+{
+    repo_path: PathBuf,
+    id: Oid,
+    size: u64,
+    mode: u32,
+}
+
+impl GitBlob
+{
+    //--------------------------------------------------------------------------
+    /// Creates the blob from its id in the repository.
+    ///
+    /// # Arguments
+    ///
+    /// - `repo` - Git repository that the blob is in.
+    /// - `id` - Id of the blob.
+    /// - `executable` - Whether the blob is committed as executable.
+    //--------------------------------------------------------------------------
+    pub fn new
+    (
+        repo: &Repository,
+        id: Oid,
+        executable: bool,
+    ) -> Result<Self, git2::Error>
+    {
+        let (size, _) = repo.odb()?.read_header(id)?;
+        Ok(GitBlob
+        {
+            repo_path: repo.path().to_path_buf(),
+            id,
+            size: size as u64,
+            mode: get_blob_mode(executable),
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Opens the content of the blob in the repository. A small blob is read
+    /// into memory, while a large blob is copied into a temporary file that is
+    /// deleted when it is closed, so that its content is not kept in memory
+    /// while it is uploaded.
+    //--------------------------------------------------------------------------
+    fn open( &self ) -> std::io::Result<Box<dyn Read + Send>>
+    {
+        let repo = Repository::open(&self.repo_path)
+            .map_err(std::io::Error::other)?;
+        let blob = repo.find_blob(self.id).map_err(std::io::Error::other)?;
+        if self.size <= MAX_MEMORY_BLOB_SIZE
+        {
+            return Ok(Box::new(Cursor::new(blob.content().to_vec())));
+        }
+
+        let mut file = tempfile::tempfile()?;
+        file.write_all(blob.content())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Box::new(file))
+    }
+}
+
+impl SourceFile
+{
+    //--------------------------------------------------------------------------
+    /// Opens the file to read its content. A blob is read from the repository
+    /// only when it is opened, one file at a time.
+    //--------------------------------------------------------------------------
+    pub fn open( &self ) -> std::io::Result<Box<dyn Read + Send>>
+    {
+        match self
+        {
+            SourceFile::Path(path) => Ok(Box::new(File::open(path)?)),
+            SourceFile::Blob(blob) => blob.open(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Reads the content of the file.
+    //--------------------------------------------------------------------------
+    pub fn read( &self ) -> std::io::Result<Vec<u8>>
+    {
+        let mut content = Vec::new();
+        self.open()?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the SHA-256 hash of the file, read chunk by chunk.
+    //--------------------------------------------------------------------------
+    pub fn hash( &self ) -> std::io::Result<String>
+    {
+        match self
+        {
+            SourceFile::Path(path) => get_file_hash(path),
+            SourceFile::Blob(_) => get_reader_hash(&mut self.open()?),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the size of the file, or the error if the file on disk no
+    /// longer exists or cannot be read.
+    //--------------------------------------------------------------------------
+    pub fn len( &self ) -> std::io::Result<u64>
+    {
+        match self
+        {
+            SourceFile::Path(path) => Ok(std::fs::metadata(path)?.len()),
+            SourceFile::Blob(blob) => Ok(blob.size),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the modification time of the file. Files in a git commit have
+    /// no modification time.
+    //--------------------------------------------------------------------------
+    pub fn modified( &self ) -> Option<SystemTime>
+    {
+        match self
+        {
+            SourceFile::Path(path) =>
+            {
+                std::fs::metadata(path).and_then(|m| m.modified()).ok()
+            },
+            SourceFile::Blob(..) => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the string to display the file.
+    //--------------------------------------------------------------------------
+    pub fn display( &self, relative_path: &str ) -> String
+    {
+        match self
+        {
+            SourceFile::Path(path) => path.to_str().unwrap().to_string(),
+            SourceFile::Blob(..) => relative_path.to_string(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the permission bits of the file. Files in a git commit are
+    /// either executable or not.
+    //--------------------------------------------------------------------------
+    pub fn mode( &self ) -> u32
+    {
+        match self
+        {
+            SourceFile::Path(path) =>
+            {
+                std::fs::metadata(path)
+                    .map(|m| m.permissions().mode() & 0o7777)
+                    .unwrap_or(0o644)
+            },
+            SourceFile::Blob(blob) => blob.mode,
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the files in the source directory keyed by the path relative to it.
+///
+/// When the artifact path is set, the files in the artifact directory are
+/// collected instead, without applying the git ignore rules since build
+/// outputs are usually ignored by git. Returns the error if an `exclude` glob
+/// is invalid.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+/// - `target_path` - Relative path from the project directory to be collected.
+//------------------------------------------------------------------------------
+pub fn get_working_tree_files
+(
+    config: &SshConfig,
+    target_path: &str,
+) -> Result<BTreeMap<String, SourceFile>, String>
+{
+    let source_path = config.source_path();
+    let git_ignore = config.artifact_path().len() == 0;
+    let mut files = BTreeMap::new();
+    for path in get_file_paths(&source_path, &config.exclude(), git_ignore)?
+    {
+        let relative_path = path
+            .to_str()
+            .unwrap()
+            .replacen(&source_path, "", 1)
+            .trim_start_matches("/")
+            .to_string();
+        if is_in_target(&relative_path, target_path) == false
+        {
+            continue;
+        }
+        files.insert(relative_path, SourceFile::Path(path));
+    }
+    Ok(files)
+}
+
+//------------------------------------------------------------------------------
+/// Gets the files in the source directory at the specified git revision keyed
+/// by the path relative to the source directory.
+///
+/// # Arguments
+///
+/// - `config` - SSH configuration.
+/// - `target_path` - Relative path from the project directory to be collected.
+/// - `revision` - Tag, branch or commit hash.
+//------------------------------------------------------------------------------
+pub fn get_revision_files
+(
+    config: &SshConfig,
+    target_path: &str,
+    revision: &str,
+) -> Result<(String, BTreeMap<String, SourceFile>), git2::Error>
+{
+    let repo = Repository::open(config.git_path())?;
+    let commit = repo.revparse_single(revision)?.peel_to_commit()?;
+    let mut tree = commit.tree()?;
+
+    //  Restricts the tree to the source directory.
+    let git_relative_path = config.get_git_relative_path();
+    if git_relative_path.len() > 0
+    {
+        tree = tree
+            .get_path(Path::new(&git_relative_path))?
+            .to_object(&repo)?
+            .peel_to_tree()?;
+    }
+
+    let excludes = get_exclude_matcher
+    (
+        &config.git_src_path(),
+        &config.exclude(),
+    )
+    .map_err(|e| git2::Error::from_str(&e))?;
+
+    //  Applies the `.resmignore` committed at the root of the source directory.
+    let mut resmignore = GitignoreBuilder::new(config.git_src_path());
+    if let Some(entry) = tree.get_name(".resmignore")
+    {
+        let blob = repo.find_blob(entry.id())?;
+        for line in String::from_utf8_lossy(blob.content()).lines()
+        {
+            resmignore.add_line(None, line).unwrap();
+        }
+    }
+    let resmignore = resmignore.build().unwrap();
+    let mut files = BTreeMap::new();
+    let mut error = None;
+    tree.walk(TreeWalkMode::PreOrder, |root, entry|
+    {
+        //  Skips directories, submodules and symbolic links.
+        if entry.kind() != Some(ObjectType::Blob)
+            || entry.filemode() == 0o120000
+        {
+            return TreeWalkResult::Ok;
+        }
+
+        let relative_path = root.to_string() + entry.name().unwrap();
+        if is_in_target(&relative_path, target_path) == false
+            || is_excluded(&excludes, &relative_path, false)
+            || resmignore
+                .matched_path_or_any_parents(&relative_path, false)
+                .is_ignore()
+        {
+            return TreeWalkResult::Ok;
+        }
+
+        let executable = entry.filemode() == 0o100755;
+        match GitBlob::new(&repo, entry.id(), executable)
+        {
+            Ok(blob) =>
+            {
+                files.insert(relative_path, SourceFile::Blob(blob));
+                TreeWalkResult::Ok
+            },
+            Err(e) =>
+            {
+                error = Some(e);
+                TreeWalkResult::Abort
+            },
+        }
+    })?;
+    if let Some(e) = error
+    {
+        return Err(e);
+    }
+
+    Ok((commit.id().to_string(), files))
+}
+
+//------------------------------------------------------------------------------
+/// Gets the path relative to the target path from the path relative to the
+/// source directory.
+//------------------------------------------------------------------------------
+pub fn get_target_relative_path
+(
+    relative_path: &str,
+    target_path: &str,
+) -> String
+{
+    let target_path = target_path.trim_end_matches("/");
+    if target_path.len() == 0
+    {
+        return relative_path.to_string();
+    }
+    relative_path
+        .strip_prefix(&(target_path.to_string() + "/"))
+        .unwrap_or(relative_path)
+        .to_string()
+}
+
+//------------------------------------------------------------------------------
+/// Checks whether the relative path is the target path or is in it.
+//------------------------------------------------------------------------------
+pub fn is_in_target( relative_path: &str, target_path: &str ) -> bool
+{
+    let target_path = target_path.trim_end_matches("/");
+    target_path.len() == 0
+        || relative_path == target_path
+        || relative_path.starts_with(&(target_path.to_string() + "/"))
+}
+
+//------------------------------------------------------------------------------
+/// Gets the permission bits of a file in a git commit.
+///
+/// # Arguments
+///
+/// - `executable` - Whether the file is executable in git.
+//------------------------------------------------------------------------------
+pub fn get_blob_mode( executable: bool ) -> u32
+{
+    if executable
+    {
+        0o755
+    }
+    else
+    {
+        0o644
+    }
+}
@@ -1,33 +1,124 @@
 //! SSH config structure.
 
 use crate::connect_info::ConnectInfo;
+use crate::hooks::Hooks;
+use crate::permissions::PermissionRule;
+use crate::inherit::Origin;
 
+use crate::schema::secret_schema;
+
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
 use serde::{ Deserialize, Serialize };
 
 //------------------------------------------------------------------------------
 /// Structure that stores operations and connection information for the
 /// connection destination.
 //------------------------------------------------------------------------------
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct SshConfig
 {
+    /// Project name.
     project: String,
+
+    /// Environment name.
     environment: String,
+
+    /// Key (`project_environment`) of the entry to inherit values from.
+    extends: Option<String>,
+
+    /// Reference path to be operated in the destination server (absolute
+    /// path).
     remote_path: Option<String>,
+
+    /// Path to the cache directory in the destination server (absolute path).
     remote_cache_path: Option<String>,
+
+    /// Mode of the cache directory created by `clear` (default: 777).
+    remote_cache_mode: Option<String>,
+
+    /// Path to the git repository (absolute path).
     git_path: Option<String>,
+
+    /// Path to the source directory in the git repository (absolute path).
     git_src_path: Option<String>,
+
+    /// Path to the backup directory (absolute path).
     backup_path: Option<String>,
+
+    /// Host name of the database server.
     db_host: Option<String>,
+
+    /// Host name of the database server for reader.
     db_host_reader: Option<String>,
+
+    /// Port number of the database server.
     db_port: Option<u16>,
+
+    /// Database name.
     db_name: Option<String>,
+
+    /// Database user name.
     db_user: Option<String>,
+
+    /// Database password.
+    #[serde(default)]
+    #[schemars(schema_with = "secret_schema")]
     db_password: Option<String>,
+
+    /// Database root user name.
     db_root_user: Option<String>,
+
+    /// Database root password.
+    #[serde(default)]
+    #[schemars(schema_with = "secret_schema")]
     db_root_password: Option<String>,
+
+    /// Information for the SSH connection to the destination server.
     connect_info: ConnectInfo,
+
+    /// Information on the step servers to be passed through when connecting.
     tunnels: Option<Vec<ConnectInfo>>,
+
+    /// Whether to deploy into `<remote_path>.releases/<timestamp>` and switch
+    /// `remote_path` as a symbolic link.
+    release_mode: Option<bool>,
+
+    /// Number of releases to keep in release mode (default: 5).
+    keep_releases: Option<usize>,
+
+    /// Globs of files not to be uploaded by `replace`. `replace --delta
+    /// --delete` does not delete the remote files matching them either, while
+    /// a full replace removes them unless they match `preserve`.
+    exclude: Option<Vec<String>>,
+
+    /// Globs of remote files that `replace` never deletes or overwrites.
+    preserve: Option<Vec<String>>,
+
+    /// Command to be run in `git_path` by `replace` before collecting the files
+    /// to upload.
+    build_command: Option<String>,
+
+    /// Path to the directory whose files are uploaded by `replace` instead of
+    /// `git_src_path` (absolute path).
+    artifact_path: Option<String>,
+
+    /// Commands to be run before `replace` and `patch` upload files.
+    pre_deploy: Option<Hooks>,
+
+    /// Commands to be run after `replace` and `patch` upload files.
+    post_deploy: Option<Hooks>,
+
+    /// Rules applied after `replace` and `patch` upload files.
+    permissions: Option<Vec<PermissionRule>>,
+
+    #[serde(skip)]
+    origins: BTreeMap<String, Origin>,
+
+    #[serde(skip)]
+    location: Option<Origin>,
 }
 
 impl SshConfig
@@ -48,6 +139,46 @@ impl SshConfig
         self.environment.clone()
     }
 
+    //--------------------------------------------------------------------------
+    /// Returns the key of the entry that this entry extends.
+    //--------------------------------------------------------------------------
+    pub fn extends( &self ) -> String
+    {
+        self.extends.clone().unwrap_or("".to_string())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the origin of each value keyed by its field path.
+    //--------------------------------------------------------------------------
+    pub fn origins( &self ) -> &BTreeMap<String, Origin>
+    {
+        &self.origins
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the origin of each value keyed by its field path.
+    //--------------------------------------------------------------------------
+    pub fn set_origins( &mut self, origins: BTreeMap<String, Origin> )
+    {
+        self.origins = origins;
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the file and position that the entry was loaded from.
+    //--------------------------------------------------------------------------
+    pub fn location( &self ) -> Option<&Origin>
+    {
+        self.location.as_ref()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the file and position that the entry was loaded from.
+    //--------------------------------------------------------------------------
+    pub fn set_location( &mut self, location: Origin )
+    {
+        self.location = Some(location);
+    }
+
     //--------------------------------------------------------------------------
     /// Returns the remote path.
     //--------------------------------------------------------------------------
@@ -72,6 +203,14 @@ impl SshConfig
             .to_string()
     }
 
+    //--------------------------------------------------------------------------
+    /// Returns the mode of the remote cache directory created by `clear`.
+    //--------------------------------------------------------------------------
+    pub fn remote_cache_mode( &self ) -> String
+    {
+        self.remote_cache_mode.clone().unwrap_or("777".to_string())
+    }
+
     //--------------------------------------------------------------------------
     /// Returns the git path.
     //--------------------------------------------------------------------------
@@ -110,6 +249,49 @@ impl SshConfig
             .to_string()
     }
 
+    //--------------------------------------------------------------------------
+    /// Returns the globs of remote files that `replace` must never delete or
+    /// overwrite.
+    //--------------------------------------------------------------------------
+    pub fn preserve( &self ) -> Vec<String>
+    {
+        self.preserve.clone().unwrap_or_default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the local build command.
+    //--------------------------------------------------------------------------
+    pub fn build_command( &self ) -> String
+    {
+        self.build_command.clone().unwrap_or("".to_string())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the path to the build artifact directory.
+    //--------------------------------------------------------------------------
+    pub fn artifact_path( &self ) -> String
+    {
+        self.artifact_path
+            .clone()
+            .unwrap_or("".to_string())
+            .trim_end_matches("/")
+            .to_string()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the path to the directory whose files are uploaded, which is the
+    /// artifact path if it is set, otherwise the git source path.
+    //--------------------------------------------------------------------------
+    pub fn source_path( &self ) -> String
+    {
+        let artifact_path = self.artifact_path();
+        if artifact_path.len() > 0
+        {
+            return artifact_path;
+        }
+        self.git_src_path()
+    }
+
     //--------------------------------------------------------------------------
     /// Returns the backup path.
     //--------------------------------------------------------------------------
@@ -201,4 +383,70 @@ impl SshConfig
     {
         &self.tunnels
     }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether to deploy into release directories.
+    //--------------------------------------------------------------------------
+    pub fn release_mode( &self ) -> bool
+    {
+        self.release_mode.unwrap_or(false)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of releases to keep.
+    //--------------------------------------------------------------------------
+    pub fn keep_releases( &self ) -> usize
+    {
+        self.keep_releases.unwrap_or(5).max(1)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the path to the releases directory, which is placed next to the
+    /// remote path and named after it so that each project has its own.
+    //--------------------------------------------------------------------------
+    pub fn releases_path( &self ) -> String
+    {
+        self.remote_path() + ".releases"
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the path to the deploy lock file, which is placed next to the
+    /// remote path so that it survives the removal of the remote directory.
+    //--------------------------------------------------------------------------
+    pub fn lock_path( &self ) -> String
+    {
+        self.remote_path() + ".resm-lock"
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the globs of files to be excluded from the upload.
+    //--------------------------------------------------------------------------
+    pub fn exclude( &self ) -> Vec<String>
+    {
+        self.exclude.clone().unwrap_or_default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the commands executed before a deploy.
+    //--------------------------------------------------------------------------
+    pub fn pre_deploy( &self ) -> Hooks
+    {
+        self.pre_deploy.clone().unwrap_or_default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the commands executed after a deploy.
+    //--------------------------------------------------------------------------
+    pub fn post_deploy( &self ) -> Hooks
+    {
+        self.post_deploy.clone().unwrap_or_default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the rules of the modes and owners applied after a deploy.
+    //--------------------------------------------------------------------------
+    pub fn permissions( &self ) -> Vec<PermissionRule>
+    {
+        self.permissions.clone().unwrap_or_default()
+    }
 }
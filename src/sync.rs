@@ -0,0 +1,292 @@
+//! Specific implementation of operations related to delta synchronization.
+
+use crate::source::SourceFile;
+use crate::transfer::get_remote_file_hash;
+
+use std::collections::{ BTreeMap, BTreeSet };
+
+use colored::Colorize;
+use futures_util::StreamExt;
+use openssh_sftp_client::file::OpenOptions;
+use openssh_sftp_client::fs::Fs;
+use openssh_sftp_client::metadata::MetaData;
+use async_recursion::async_recursion;
+
+//------------------------------------------------------------------------------
+/// Files that differ between the local directory and the remote directory.
+///
+/// All paths are relative to the remote base path.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct SyncPlan
+{
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub deleted: Vec<String>,
+    pub deleted_dirs: Vec<String>,
+}
+
+impl SyncPlan
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether there is nothing to synchronize.
+    //--------------------------------------------------------------------------
+    pub fn is_empty( &self ) -> bool
+    {
+        self.added.len() == 0
+            && self.changed.len() == 0
+            && self.deleted.len() == 0
+            && self.deleted_dirs.len() == 0
+    }
+
+    //--------------------------------------------------------------------------
+    /// Keeps the remote paths for which `is_deleted` returns false, and the
+    /// directories that contain them.
+    ///
+    /// # Arguments
+    ///
+    /// - `is_deleted` - Returns whether the path, a directory if the second
+    ///   argument is true, can be deleted.
+    //--------------------------------------------------------------------------
+    pub fn retain_deletions<F>( &mut self, is_deleted: F )
+    where
+        F: Fn(&str, bool) -> bool,
+    {
+        let mut kept = Vec::new();
+        self.deleted.retain(|path|
+        {
+            let deleted = is_deleted(path, false);
+            if deleted == false
+            {
+                kept.push(path.clone());
+            }
+            deleted
+        });
+        self.deleted_dirs.retain(|path|
+        {
+            let deleted = is_deleted(path, true);
+            if deleted == false
+            {
+                kept.push(path.clone());
+            }
+            deleted
+        });
+
+        //  A directory cannot be removed while it contains a kept path.
+        self.deleted_dirs.retain(|dir|
+        {
+            let prefix = dir.clone() + "/";
+            kept.iter().any(|path| path.starts_with(&prefix)) == false
+        });
+    }
+
+    //--------------------------------------------------------------------------
+    /// Prints the summary of the plan.
+    //--------------------------------------------------------------------------
+    pub fn print( &self )
+    {
+        println!
+        (
+            "{} : {} added, {} changed, {} deleted, {} unchanged",
+            "Summary    ".green(),
+            self.added.len(),
+            self.changed.len(),
+            self.deleted.len(),
+            self.unchanged.len(),
+        );
+        for path in &self.added
+        {
+            println!("    {} {}", "+".green(), path);
+        }
+        for path in &self.changed
+        {
+            println!("    {} {}", "~".yellow(), path);
+        }
+        for path in &self.deleted
+        {
+            println!("    {} {}", "-".red(), path);
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets all entries under the remote directory.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `base` - Remote base path that the keys are relative to.
+/// - `dir` - Remote directory to walk.
+/// - `entries` - Map from relative paths to metadata to be filled.
+//------------------------------------------------------------------------------
+#[async_recursion]
+pub async fn get_remote_entries
+(
+    fs: &mut Fs,
+    base: &str,
+    dir: &str,
+    entries: &mut BTreeMap<String, MetaData>,
+)
+{
+    let read_dir = match fs.open_dir(dir).await
+    {
+        Ok(dir) => dir.read_dir(),
+        Err(_) => return,
+    };
+    let dir_entries: Vec<_> = read_dir.collect().await;
+
+    for entry in dir_entries.into_iter().flatten()
+    {
+        let filename = entry.filename().to_str().unwrap().to_string();
+        if filename == "." || filename == ".."
+        {
+            continue;
+        }
+
+        let path = dir.to_string() + "/" + &filename;
+        let relative_path = path
+            .replacen(base, "", 1)
+            .trim_start_matches("/")
+            .to_string();
+        let metadata = entry.metadata();
+        entries.insert(relative_path, metadata);
+
+        if metadata.file_type().map(|t| t.is_dir()).unwrap_or(false)
+        {
+            get_remote_entries(fs, base, &path, entries).await;
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Compares local files with the remote directory and computes the files to
+/// upload and delete.
+///
+/// Files are compared by size and modification time, or by SHA-256 hash when
+/// `checksum` is enabled or the local file has no modification time. Remote
+/// files are hashed chunk by chunk, and a file that cannot be read is treated
+/// as changed.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `options` - SFTP open options.
+/// - `local_files` - Map from relative paths to local files.
+/// - `remote_base` - Remote base path.
+/// - `remote_dir` - Remote directory to compare (the base or a sub directory).
+/// - `checksum` - Whether to compare files by hash.
+/// - `delete` - Whether to delete remote files that do not exist locally.
+//------------------------------------------------------------------------------
+pub async fn compute_sync_plan
+(
+    fs: &mut Fs,
+    options: &OpenOptions,
+    local_files: &BTreeMap<String, SourceFile>,
+    remote_base: &str,
+    remote_dir: &str,
+    checksum: bool,
+    delete: bool,
+) -> SyncPlan
+{
+    let mut remote_entries = BTreeMap::new();
+    get_remote_entries(fs, remote_base, remote_dir, &mut remote_entries).await;
+
+    let mut plan = SyncPlan::default();
+    for (relative_path, local_file) in local_files
+    {
+        let remote_metadata = match remote_entries.get(relative_path)
+        {
+            Some(metadata) if metadata.file_type()
+                .map(|t| t.is_file())
+                .unwrap_or(false) => metadata,
+            _ =>
+            {
+                plan.added.push(relative_path.clone());
+                continue;
+            },
+        };
+
+        if remote_metadata.len() != local_file.len().ok()
+        {
+            plan.changed.push(relative_path.clone());
+            continue;
+        }
+
+        let local_modified = local_file.modified();
+        let is_changed = if checksum || local_modified.is_none()
+        {
+            let remote_path = remote_base.to_string() + "/" + relative_path;
+            match get_remote_file_hash(options, &remote_path).await
+            {
+                Ok(remote_hash) =>
+                {
+                    local_file.hash().ok() != Some(remote_hash)
+                },
+                Err(e) =>
+                {
+                    println!
+                    (
+                        "{} : Failed to read {}: {}",
+                        "Warning".yellow(),
+                        remote_path,
+                        e,
+                    );
+                    true
+                },
+            }
+        }
+        else
+        {
+            match (remote_metadata.modified(), local_modified)
+            {
+                (Some(remote), Some(local)) => remote.as_system_time() < local,
+                _ => true,
+            }
+        };
+
+        if is_changed
+        {
+            plan.changed.push(relative_path.clone());
+        }
+        else
+        {
+            plan.unchanged.push(relative_path.clone());
+        }
+    }
+
+    if delete
+    {
+        //  Directories that contain at least one local file.
+        let mut local_dirs = BTreeSet::new();
+        for relative_path in local_files.keys()
+        {
+            let mut dir = relative_path.as_str();
+            while let Some(pos) = dir.rfind('/')
+            {
+                dir = &dir[..pos];
+                local_dirs.insert(dir.to_string());
+            }
+        }
+
+        for (relative_path, metadata) in &remote_entries
+        {
+            if metadata.file_type().map(|t| t.is_dir()).unwrap_or(false)
+            {
+                if local_dirs.contains(relative_path) == false
+                {
+                    plan.deleted_dirs.push(relative_path.clone());
+                }
+            }
+            else if local_files.contains_key(relative_path) == false
+            {
+                plan.deleted.push(relative_path.clone());
+            }
+        }
+
+        //  Removes the deepest directories first.
+        plan.deleted_dirs.reverse();
+    }
+
+    plan
+}
@@ -0,0 +1,229 @@
+//! Chunked file transfers between the local machine and the remote server, so
+//! that memory use does not depend on the file size.
+
+use crate::progress::Progress;
+use crate::permissions::to_permissions;
+
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use bytes::BytesMut;
+use openssh_sftp_client::file::OpenOptions;
+use openssh_sftp_client::Error;
+use sha2::{ Digest, Sha256 };
+use tokio::io::{ AsyncRead, AsyncReadExt };
+
+//------------------------------------------------------------------------------
+/// Size of the chunks read and written at a time.
+//------------------------------------------------------------------------------
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+//------------------------------------------------------------------------------
+/// Uploads the local file to the remote server chunk by chunk, and carries its
+/// permission bits over. Returns the number of bytes uploaded.
+///
+/// # Arguments
+///
+/// - `options` - SFTP open options.
+/// - `from` - Local file path.
+/// - `to` - Remote file path.
+/// - `progress` - Progress that the transferred bytes are added to.
+//------------------------------------------------------------------------------
+pub async fn upload_file
+(
+    options: &OpenOptions,
+    from: &Path,
+    to: &str,
+    progress: &Progress,
+) -> Result<u64, Error>
+{
+    let mut local_file = File::open(from)?;
+    let mode = local_file.metadata()?.permissions().mode() & 0o7777;
+    upload_reader(options, &mut local_file, mode, to, progress).await
+}
+
+//------------------------------------------------------------------------------
+/// Uploads the content of the reader to the remote server chunk by chunk, and
+/// sets the permission bits. Returns the number of bytes uploaded.
+///
+/// # Arguments
+///
+/// - `options` - SFTP open options.
+/// - `reader` - Content to upload.
+/// - `mode` - Permission bits of the remote file.
+/// - `to` - Remote file path.
+/// - `progress` - Progress that the transferred bytes are added to.
+//------------------------------------------------------------------------------
+pub async fn upload_reader<R>
+(
+    options: &OpenOptions,
+    reader: &mut R,
+    mode: u32,
+    to: &str,
+    progress: &Progress,
+) -> Result<u64, Error>
+where
+    R: Read + ?Sized,
+{
+    let mut remote_file = options
+        .clone()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(to)
+        .await?;
+
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut total = 0;
+    loop
+    {
+        let n = reader.read(&mut buf)?;
+        if n == 0
+        {
+            break;
+        }
+        remote_file.write_all(&buf[..n]).await?;
+        total += n as u64;
+        progress.inc_bytes(n as u64);
+    }
+    remote_file.set_permissions(to_permissions(mode)).await?;
+    remote_file.close().await?;
+    Ok(total)
+}
+
+//------------------------------------------------------------------------------
+/// Downloads the remote file to the local machine chunk by chunk. Returns the
+/// number of bytes downloaded.
+///
+/// # Arguments
+///
+/// - `options` - SFTP open options.
+/// - `from` - Remote file path.
+/// - `to` - Local file path.
+/// - `progress` - Progress that the transferred bytes are added to.
+//------------------------------------------------------------------------------
+pub async fn download_file
+(
+    options: &OpenOptions,
+    from: &str,
+    to: &Path,
+    progress: &Progress,
+) -> Result<u64, Error>
+{
+    let mut remote_file = options.clone().read(true).open(from).await?;
+    let mut local_file = File::create(to)?;
+
+    let mut total = 0;
+    while let Some(buf) = remote_file
+        .read(CHUNK_SIZE as u32, BytesMut::with_capacity(CHUNK_SIZE))
+        .await?
+    {
+        local_file.write_all(&buf)?;
+        total += buf.len() as u64;
+        progress.inc_bytes(buf.len() as u64);
+    }
+    remote_file.close().await?;
+    Ok(total)
+}
+
+//------------------------------------------------------------------------------
+/// Writes the output of a remote command to the local file chunk by chunk.
+/// Returns the number of bytes written.
+///
+/// # Arguments
+///
+/// - `reader` - Output of the remote command.
+/// - `to` - Local file path.
+/// - `progress` - Progress that the written bytes are added to.
+//------------------------------------------------------------------------------
+pub async fn save_stream<R>
+(
+    reader: &mut R,
+    to: &Path,
+    progress: &Progress,
+) -> std::io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut local_file = File::create(to)?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut total = 0;
+    loop
+    {
+        let n = reader.read(&mut buf).await?;
+        if n == 0
+        {
+            break;
+        }
+        local_file.write_all(&buf[..n])?;
+        total += n as u64;
+        progress.inc_bytes(n as u64);
+    }
+    Ok(total)
+}
+
+//------------------------------------------------------------------------------
+/// Gets the SHA-256 hash of the local file without loading it into memory.
+///
+/// # Arguments
+///
+/// - `path` - Local file path.
+//------------------------------------------------------------------------------
+pub fn get_file_hash( path: &Path ) -> std::io::Result<String>
+{
+    get_reader_hash(&mut File::open(path)?)
+}
+
+//------------------------------------------------------------------------------
+/// Gets the SHA-256 hash of the content of the reader chunk by chunk.
+///
+/// # Arguments
+///
+/// - `reader` - Content to hash.
+//------------------------------------------------------------------------------
+pub fn get_reader_hash<R>( reader: &mut R ) -> std::io::Result<String>
+where
+    R: Read + ?Sized,
+{
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop
+    {
+        let n = reader.read(&mut buf)?;
+        if n == 0
+        {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+//------------------------------------------------------------------------------
+/// Gets the SHA-256 hash of the remote file chunk by chunk through the SFTP
+/// file handle, without loading it into memory.
+///
+/// # Arguments
+///
+/// - `options` - SFTP open options.
+/// - `path` - Remote file path.
+//------------------------------------------------------------------------------
+pub async fn get_remote_file_hash
+(
+    options: &OpenOptions,
+    path: &str,
+) -> Result<String, Error>
+{
+    let mut remote_file = options.clone().read(true).open(path).await?;
+    let mut hasher = Sha256::new();
+    while let Some(buf) = remote_file
+        .read(CHUNK_SIZE as u32, BytesMut::with_capacity(CHUNK_SIZE))
+        .await?
+    {
+        hasher.update(&buf);
+    }
+    remote_file.close().await?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
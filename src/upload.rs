@@ -1,26 +1,120 @@
 //! Specific implementation of operations related to application update.
 
 use crate::ssh_config::SshConfig;
+use crate::release::{
+    new_release_path,
+    check_release_target,
+    get_current_release,
+    switch_release,
+    prune_releases,
+};
+use crate::source::{
+    SourceFile,
+    get_working_tree_files,
+    get_revision_files,
+    is_in_target,
+    get_target_relative_path,
+};
+use crate::changeset::{
+    get_patch_file_changes,
+    get_working_tree_changes,
+    get_revision_range_changes,
+};
+use crate::sync::{ SyncPlan, compute_sync_plan };
+use crate::transfer::{ upload_file, upload_reader };
+use crate::progress::Progress;
+use crate::manifest::{
+    MANIFEST_FILE,
+    DeployManifest,
+    read_manifest,
+    write_manifest,
+};
+use crate::lock::{ acquire_lock, release_lock };
+use crate::safety::{
+    check_remote_path,
+    check_removal,
+    print_refused,
+    remove_remote,
+};
+use crate::preserve::{
+    PreservePlan,
+    get_preserve_matcher,
+    get_preserve_plan,
+    remove_unpreserved,
+    copy_preserved,
+    join_path,
+    get_parent_path,
+};
+use crate::archive::{
+    ArchiveFormat,
+    create_archive,
+    check_extractor,
+    extract_archive,
+    get_staging_path,
+    get_remote_archive_path,
+};
+use crate::hooks::{ run_hooks, run_local_command, print_hooks_plan };
+use crate::permissions::{
+    apply_permissions,
+    print_permissions_plan,
+    check_mode,
+};
 use crate::util::{
     get_session,
     get_sftp_session,
-    get_file_paths,
+    get_exclude_matcher,
+    is_excluded,
+    IgnoreRules,
     print_sep,
+    print_dry_run_command,
+    print_dry_run_transfer,
     confirm,
 };
 
+use std::collections::{ BTreeMap, BTreeSet, HashSet };
 use std::path::{ Path, PathBuf };
-use std::time::SystemTime;
-use std::fs::File;
-use std::io::{ Read, Write };
 
 use colored::Colorize;
-use git2::{ Repository, StatusOptions };
-use zip::write::{ ZipWriter, FileOptions };
+use futures_util::stream::{ self, StreamExt };
 use openssh::Session;
 use openssh_sftp_client::fs::Fs;
+use openssh_sftp_client::file::OpenOptions;
+use tempfile::NamedTempFile;
 use async_recursion::async_recursion;
 
+//------------------------------------------------------------------------------
+/// Options for replacing the remote directory.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct ReplaceOptions
+{
+    /// Relative path from the project directory that you want to upload.
+    pub target_path: String,
+
+    /// Format of the archive to upload the files in, instead of uploading
+    /// them one by one.
+    pub archive: Option<ArchiveFormat>,
+
+    /// Whether to upload only files that differ from the remote files.
+    pub delta: bool,
+
+    /// Whether to delete remote files that do not exist locally (delta mode).
+    pub delete: bool,
+
+    /// Whether to compare files by hash instead of size and modification time
+    /// (delta mode).
+    pub checksum: bool,
+
+    /// Tag, branch or commit hash to upload instead of the working tree.
+    pub revision: String,
+
+    /// Number of files uploaded at the same time.
+    pub jobs: usize,
+
+    /// Whether to only print what would be done.
+    pub dry_run: bool,
+}
+
 //------------------------------------------------------------------------------
 /// Uploads all files in the local repository to the remote server.
 //------------------------------------------------------------------------------
@@ -28,134 +122,923 @@ pub async fn upload_all
 (
     project: &str,
     config: &SshConfig,
-    target_path: String,
-    zip: bool,
+    options: &ReplaceOptions,
 )
 {
-    let git_src_path = config.git_src_path();
-    let remote_path = config.remote_path();
-    let mut remote_target_path = config.remote_path();
+    let target_path = &options.target_path;
+    let source_path = config.source_path();
+    let release_mode = config.release_mode();
+
+    //  In release mode, files are uploaded into a new release directory and
+    //  the remote path is switched to it at the end.
+    let remote_path = if release_mode
+    {
+        new_release_path(config)
+    }
+    else
+    {
+        config.remote_path()
+    };
+    let mut remote_target_path = remote_path.clone();
     if target_path.len() > 0
     {
-        remote_target_path = remote_target_path + "/" + &target_path;
+        remote_target_path = remote_target_path + "/" + target_path;
+    }
+
+    //  Refuses to run on unsafe remote paths before doing anything.
+    let checked = if options.delta
+    {
+        check_remote_path(&config.remote_path())
+    }
+    else
+    {
+        check_removal(config, &remote_target_path)
+    };
+    if let Err(e) = checked
+    {
+        print_refused(config, &e);
+        return;
+    }
+
+    //  The files of a revision are read from git, so neither the build nor
+    //  the artifact directory can be used with it.
+    if options.revision.len() > 0
+    {
+        for (field, value) in
+        [
+            ("build_command", config.build_command()),
+            ("artifact_path", config.artifact_path()),
+        ]
+        {
+            if value.len() > 0
+            {
+                println!
+                (
+                    "{} : --ref cannot be used with {}.",
+                    "Error".red(),
+                    field,
+                );
+                return;
+            }
+        }
+    }
+
+    //  Builds the artifacts before collecting the files to upload.
+    let build_command = config.build_command();
+    if build_command.len() > 0
+    {
+
+        if options.dry_run
+        {
+            println!
+            (
+                "{} : (in {}) {}",
+                "Build  ".yellow(),
+                &config.git_path(),
+                &build_command,
+            );
+        }
+        else
+        {
+            println!("{} : {}", "Build".green(), &build_command);
+            if run_local_command(&build_command, &config.git_path()) == false
+            {
+                println!("Aborted.");
+                return;
+            }
+        }
+    }
+
+    //  Collects local files keyed by the path relative to the source directory,
+    //  either from the working tree or from the specified revision.
+    let mut commit_id = None;
+    let mut local_files = if options.revision.len() > 0
+    {
+        match get_revision_files(config, target_path, &options.revision)
+        {
+            Ok((id, files)) =>
+            {
+                commit_id = Some(id);
+                files
+            },
+            Err(e) =>
+            {
+                println!("{} : {}", "Error".red(), e.message());
+                return;
+            },
+        }
+    }
+    else
+    {
+        match get_working_tree_files(config, target_path)
+        {
+            Ok(files) => files,
+            Err(e) =>
+            {
+                print_refused(config, &e);
+                return;
+            },
+        }
+    };
+
+    //  Preserved remote files are never overwritten.
+    let preserve = config.preserve();
+    let preserve_matcher = match get_preserve_matcher(config)
+    {
+        Ok(matcher) => matcher,
+        Err(e) =>
+        {
+            print_refused(config, &e);
+            return;
+        },
+    };
+    if preserve.len() > 0
+    {
+        local_files.retain(|relative_path, _|
+        {
+            is_excluded(&preserve_matcher, relative_path, false) == false
+        });
     }
-    let remote_cache_path = config.remote_cache_path();
 
-    //  Removes the remote directory.
     print_sep();
     println!("{} : {}", "Project    ".green(), &config.project());
     println!("{} : {}", "Environment".green(), &config.environment());
-    println!("{} : {}", "Remote path".green(), &remote_path);
-    println!("{} : {}", "target path".green(), &target_path);
+    println!("{} : {}", "Local path ".green(), &source_path);
+    println!("{} : {}", "Remote path".green(), &config.remote_path());
+    if release_mode
+    {
+        println!("{} : {}", "Release    ".green(), &remote_path);
+    }
+    println!("{} : {}", "target path".green(), target_path);
+    if let Some(commit_id) = &commit_id
+    {
+        println!
+        (
+            "{} : {} ({})",
+            "Revision   ".green(),
+            &options.revision,
+            commit_id,
+        );
+    }
+    if preserve.len() > 0
+    {
+        println!("{} : {}", "Preserve   ".green(), preserve.join(", "));
+    }
+
+    let mut current_target_path = config.remote_path();
+    if target_path.len() > 0
+    {
+        current_target_path = current_target_path + "/" + target_path;
+    }
+
+    //  In delta mode, compares local files with the files currently deployed.
+    let mut sync_plan = None;
+    if options.delta
+    {
+        let sftp = get_sftp_session(project).await;
+        let mut plan = compute_sync_plan
+        (
+            &mut sftp.fs(),
+            &sftp.options(),
+            &local_files,
+            &config.remote_path(),
+            &current_target_path,
+            options.checksum,
+            options.delete,
+        ).await;
+        sftp.close().await.unwrap();
+
+        //  Remote files that are not uploaded because of the ignore files or
+        //  the `exclude` globs, and files matching the `preserve` globs are
+        //  never deleted.
+        let excludes = match get_exclude_matcher
+        (
+            &source_path,
+            &config.exclude(),
+        )
+        {
+            Ok(matcher) => matcher,
+            Err(e) =>
+            {
+                print_refused(config, &e);
+                return;
+            },
+        };
+        let git_ignore = config.artifact_path().len() == 0;
+        let ignore_rules = IgnoreRules::new(&source_path, git_ignore);
+        plan.retain_deletions(|path, is_dir|
+        {
+            path != MANIFEST_FILE
+                && ignore_rules.is_ignored(path, is_dir) == false
+                && is_excluded(&excludes, path, is_dir) == false
+                && is_excluded(&preserve_matcher, path, is_dir) == false
+        });
+
+        plan.print();
+        if plan.is_empty()
+        {
+            print_sep();
+            println!("No files to upload.");
+            return;
+        }
+        sync_plan = Some(plan);
+    }
+
+    //  Otherwise, finds the preserved paths in the remote target directory so
+    //  that only the others are removed.
+    let mut preserve_plan = None;
+    if options.delta == false && preserve.len() > 0
+    {
+        let sftp = get_sftp_session(project).await;
+        let plan = get_preserve_plan
+        (
+            &mut sftp.fs(),
+            &config.remote_path(),
+            &current_target_path,
+            &preserve_matcher,
+        ).await;
+        sftp.close().await.unwrap();
+
+        plan.print();
+        preserve_plan = Some(plan);
+    }
     print_sep();
-    if confirm("Are you sure you want to remove the remote directory?") == false
+
+    //  When the target is a single file, the files are deployed into its
+    //  directory.
+    let target_dir = get_target_dir(&local_files, target_path);
+    let remote_target_dir = join_path(&remote_path, &target_dir);
+
+    if options.dry_run
+    {
+        print_replace_plan
+        (
+            config,
+            options,
+            &local_files,
+            sync_plan.as_ref(),
+            preserve_plan.as_ref(),
+            &remote_path,
+            &remote_target_path,
+        );
+        return;
+    }
+
+    let message = if release_mode
+    {
+        "Are you sure you want to deploy a new release?"
+    }
+    else if options.delta
+    {
+        "Are you sure you want to synchronize the remote directory?"
+    }
+    else
+    {
+        "Are you sure you want to remove the remote directory?"
+    };
+    if confirm(message) == false
     {
         println!("Canceled.");
         return;
     }
 
     let session = get_session(project).await;
-    session
-        .command("rm")
-        .args(["-r", &remote_target_path])
-        .output()
-        .await
-        .unwrap();
+    if acquire_lock(&session, config, "replace").await == false
+    {
+        session.close().await.unwrap();
+        return;
+    }
+
+    if release_mode && check_release_target(&session, config).await == false
+    {
+        println!("Aborted.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
 
-    println!("Removed.\n");
+    //  Prepares the archive before any remote change, so that the remote
+    //  directory is not removed when it cannot be extracted.
+    let archive = match options.archive
+    {
+        Some(format) =>
+        {
+            if check_extractor(&session, format).await == false
+            {
+                println!("Aborted.");
+                release_lock(&session, config).await;
+                session.close().await.unwrap();
+                return;
+            }
+
+            let files: Vec<_> = local_files
+                .iter()
+                .map(|(relative_path, file)|
+                {
+                    (get_target_relative_path(relative_path, &target_dir), file)
+                })
+                .collect();
+            match create_archive(format, &files)
+            {
+                Ok(archive_file) => Some((format, archive_file)),
+                Err(e) =>
+                {
+                    println!
+                    (
+                        "{} : Failed to create the archive: {}",
+                        "Error".red(),
+                        e,
+                    );
+                    release_lock(&session, config).await;
+                    session.close().await.unwrap();
+                    return;
+                },
+            }
+        },
+        None => None,
+    };
+
+    //  Uploads the archive and extracts it into the staging directory before
+    //  the target is removed, so that the target is kept when the upload or
+    //  the extraction fails.
+    let staging_path = get_staging_path(&remote_target_dir);
+    if let Some((format, archive_file)) = &archive
+    {
+        if stage_archive
+        (
+            &session,
+            project,
+            config,
+            *format,
+            archive_file,
+            &staging_path,
+        ).await == false
+        {
+            println!("Aborted.");
+            release_lock(&session, config).await;
+            session.close().await.unwrap();
+            return;
+        }
+    }
+
+    if run_hooks
+    (
+        &session,
+        "pre_deploy",
+        &config.pre_deploy(),
+        &config.git_path(),
+        &config.remote_path(),
+    ).await == false
+    {
+        println!("Aborted.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
+
+    if release_mode
+    {
+        //  When only a part of the project is replaced or only the differences
+        //  are uploaded, the new release starts from a copy of the current
+        //  release.
+        let current = get_current_release(&session, config).await;
+        if let (Some(current), true) =
+            (&current, target_path.len() > 0 || options.delta)
+        {
+            if copy_release(&session, current, &remote_path).await == false
+            {
+                println!("Aborted.");
+                release_lock(&session, config).await;
+                session.close().await.unwrap();
+                return;
+            }
+            if options.delta == false
+            {
+                remove_target
+                (
+                    &session,
+                    config,
+                    &remote_path,
+                    &remote_target_path,
+                    &preserve_plan,
+                ).await;
+            }
+        }
+        else if let (Some(current), Some(plan)) = (&current, &preserve_plan)
+        {
+            //  The preserved files are carried over from the current release.
+            if copy_preserved(&session, current, &remote_path, plan).await
+                == false
+            {
+                println!("Aborted.");
+                release_lock(&session, config).await;
+                session.close().await.unwrap();
+                return;
+            }
+        }
+    }
+    else if options.delta == false
+    {
+        //  Removes the remote directory.
+        remove_target
+        (
+            &session,
+            config,
+            &remote_path,
+            &remote_target_path,
+            &preserve_plan,
+        ).await;
+
+        println!("Removed.\n");
+    }
     println!("Upload files.");
 
     //  Uploads all files.
+    let mut failed = BTreeSet::new();
     let sftp = get_sftp_session(project).await;
     {
         let mut fs = sftp.fs();
-        mkdir_all(&mut fs, &Path::new(&remote_target_path)).await;
-        let paths = get_file_paths(&git_src_path);
+        let open_options = sftp.options();
+        let mut dirs = HashSet::new();
+
+        mkdir_all(&mut fs, Path::new(&remote_target_dir), &mut dirs).await;
 
-        if zip
+        if let Some(plan) = sync_plan
         {
-            //  Creates a temporary zip file.
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            let temp_file = "temp_".to_string() + &now.to_string() + ".zip";
+            let uploads: Vec<_> = plan.added
+                .iter()
+                .chain(plan.changed.iter())
+                .map(|relative_path|
+                {
+                    (relative_path, local_files.get(relative_path).unwrap())
+                })
+                .collect();
+            failed = upload_sources
+            (
+                &mut fs,
+                &open_options,
+                &remote_path,
+                uploads,
+                &mut dirs,
+                options.jobs,
+            ).await;
 
-            let zip_file = File::create(&temp_file).unwrap();
-            let mut zip = ZipWriter::new(zip_file);
-            for path in paths
+            for relative_path in &plan.deleted
             {
-                let path_str = path.to_str().unwrap();
-
-                //  Skip if the target path is specified and the path is not in
-                //  the target path.
-                if target_path.len() > 0
+                let remote_path_str = remote_path.clone() + "/" + relative_path;
+                if let Err(e) = check_removal(config, &remote_path_str)
                 {
-                    let target_path = git_src_path.clone() + "/" + &target_path;
-                    if path_str.starts_with(&target_path) == false
-                    {
-                        continue;
-                    }
+                    print_refused(config, &e);
+                    continue;
+                }
+                println!("{} : {}", "Deleting".red(), &remote_path_str);
+                if let Err(e) = fs.remove_file(&remote_path_str).await
+                {
+                    println!
+                    (
+                        "{} : Failed to delete {}: {}",
+                        "Error".red(),
+                        &remote_path_str,
+                        e,
+                    );
                 }
-
-                //  Adds the file to the zip file.
-                let path_str = path_str.replace(&git_src_path, "");
-                zip.start_file(path_str, FileOptions::default()).unwrap();
-                let content = std::fs::read(path.to_str().unwrap()).unwrap();
-                zip.write_all(&content).unwrap();
             }
-            zip.finish().unwrap();
-
-            //  Uploads the zip file.
-            let remote_path_str = remote_path.clone() + "/" + &temp_file;
-            upload(&mut fs, &temp_file, &remote_path_str, false).await;
-
-            session
-                .command("unzip")
-                .args(["-o", &remote_path_str, "-d", &remote_path])
-                .output()
-                .await
-                .unwrap();
-            session
-                .command("rm")
-                .args(["-r", &remote_path_str])
-                .output()
-                .await
-                .unwrap();
-            std::fs::remove_file(&temp_file).unwrap();
-        }
-        else
-        {
-            for path in paths
+            for relative_path in &plan.deleted_dirs
             {
-                let path_str = path.to_str().unwrap();
-                let remote_path_str = remote_path.clone()
-                    + &path_str.replace(&git_src_path, "");
-
-                //  Skip if the target path is specified and the path is not in
-                //  the target path.
-                if target_path.len() > 0
+                let remote_path_str = remote_path.clone() + "/" + relative_path;
+                if let Err(e) = check_removal(config, &remote_path_str)
                 {
-                    let target_path = git_src_path.clone() + "/" + &target_path;
-                    if path_str.starts_with(&target_path) == false
-                    {
-                        continue;
-                    }
+                    print_refused(config, &e);
+                    continue;
                 }
+                println!("{} : {}", "Deleting".red(), &remote_path_str);
+                if let Err(e) = fs.remove_dir(&remote_path_str).await
+                {
+                    println!
+                    (
+                        "{} : Failed to delete {}: {}",
+                        "Error".red(),
+                        &remote_path_str,
+                        e,
+                    );
+                }
+            }
+        }
+        else if archive.is_some()
+        {
+            //  Moves the extracted files from the staging directory into the
+            //  target.
+            if swap_staging
+            (
+                &session,
+                config,
+                &staging_path,
+                &remote_target_dir,
+            ).await == false
+            {
+                drop(fs);
+                sftp.close().await.unwrap();
+                println!("Aborted.");
+                release_lock(&session, config).await;
+                session.close().await.unwrap();
+                return;
+            }
+        }
+        else
+        {
+            failed = upload_sources
+            (
+                &mut fs,
+                &open_options,
+                &remote_path,
+                local_files.iter().collect(),
+                &mut dirs,
+                options.jobs,
+            ).await;
+        }
 
-                upload(&mut fs, path_str, &remote_path_str, path.is_dir())
-                    .await;
+        //  Records what was deployed. When only a part of the project is
+        //  replaced, the records of the other files are kept.
+        let mut manifest = DeployManifest::new(config, "replace");
+        if let Some(commit_id) = &commit_id
+        {
+            manifest.commit = Some(commit_id.clone());
+            manifest.branch = Some(options.revision.clone());
+            manifest.dirty = false;
+        }
+        if target_path.len() > 0
+        {
+            if let Some(previous) =
+                read_manifest(&mut fs, &config.remote_path()).await
+            {
+                manifest.files = previous.files
+                    .into_iter()
+                    .filter(|(path, _)| !is_in_target(path, target_path))
+                    .collect();
             }
         }
+        record_files(&mut manifest, &local_files, &failed);
+        write_manifest(&mut fs, &remote_path, &manifest).await;
     }
     sftp.close().await.unwrap();
 
+    //  A release with missing files is never switched to.
+    if failed.len() > 0
+    {
+        println!
+        (
+            "{} : {} file(s) failed to upload.",
+            "Error".red(),
+            failed.len(),
+        );
+        if release_mode
+        {
+            println!
+            (
+                "{} is not switched to the new release. Aborted.",
+                &config.remote_path(),
+            );
+            release_lock(&session, config).await;
+            session.close().await.unwrap();
+            return;
+        }
+    }
+
+    if apply_permissions(&session, &config.permissions(), &remote_path).await
+        == false
+    {
+        println!("Aborted.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
+
+    //  Switches the remote path to the new release.
+    if release_mode
+    {
+        if switch_release(&session, config, &remote_path).await == false
+        {
+            println!("Failed to switch the release.");
+            release_lock(&session, config).await;
+            session.close().await.unwrap();
+            return;
+        }
+        prune_releases(&session, config).await;
+    }
+
+    if run_hooks
+    (
+        &session,
+        "post_deploy",
+        &config.post_deploy(),
+        &config.git_path(),
+        &config.remote_path(),
+    ).await == false
+    {
+        println!("Aborted.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
+
     println!("Done.\n");
-    clear_cache(&session, &remote_cache_path).await;
+    clear_cache(&session, config).await;
+    release_lock(&session, config).await;
     session.close().await.unwrap();
     println!("Done.");
 }
 
+//------------------------------------------------------------------------------
+/// Copies the current release into the new release. Returns false after
+/// printing the error if the copy failed.
+//------------------------------------------------------------------------------
+async fn copy_release( session: &Session, current: &str, release: &str ) -> bool
+{
+    let from = current.to_string() + "/.";
+    let commands =
+    [
+        ("mkdir", vec!["-p", release]),
+        ("cp", vec!["-a", from.as_str(), release]),
+    ];
+    for (program, args) in commands
+    {
+        let output = session
+            .command(program)
+            .args(args)
+            .output()
+            .await
+            .unwrap();
+        if output.status.success() == false
+        {
+            println!
+            (
+                "{} : Failed to copy {} into {}: {}",
+                "Error".red(),
+                current,
+                release,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+            return false;
+        }
+    }
+    true
+}
+
+//------------------------------------------------------------------------------
+/// Removes the remote file after checking that it is safe to remove, and
+/// prints the error if it could not be removed.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `config` - SSH configuration.
+/// - `path` - Remote file to be removed.
+//------------------------------------------------------------------------------
+async fn remove_remote_file( fs: &mut Fs, config: &SshConfig, path: &str )
+{
+    if let Err(e) = check_removal(config, path)
+    {
+        print_refused(config, &e);
+        return;
+    }
+    println!("{} : {}", "Deleting".red(), path);
+    if let Err(e) = fs.remove_file(path).await
+    {
+        println!("{} : Failed to delete {}: {}", "Error".red(), path, e);
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the directory relative to the source directory that the files are
+/// deployed into. It is the target path itself, or its parent directory when
+/// the target is a single file.
+///
+/// # Arguments
+///
+/// - `local_files` - Local files keyed by the path relative to the source
+///                   directory.
+/// - `target_path` - Relative path from the project directory to be deployed.
+//------------------------------------------------------------------------------
+fn get_target_dir
+(
+    local_files: &BTreeMap<String, SourceFile>,
+    target_path: &str,
+) -> String
+{
+    let target_file = target_path.trim_matches('/');
+    if local_files.contains_key(target_file) == false
+    {
+        return target_path.to_string();
+    }
+    match target_file.rfind('/')
+    {
+        Some(pos) => target_file[..pos].to_string(),
+        None => "".to_string(),
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Removes the remote target directory except the preserved paths.
+//------------------------------------------------------------------------------
+async fn remove_target
+(
+    session: &Session,
+    config: &SshConfig,
+    remote_path: &str,
+    remote_target_path: &str,
+    preserve_plan: &Option<PreservePlan>,
+)
+{
+    match preserve_plan
+    {
+        Some(plan) =>
+        {
+            remove_unpreserved(session, config, remote_path, plan).await;
+        },
+        None =>
+        {
+            remove_remote(session, config, remote_target_path).await;
+        },
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Prints what `upload_all` would do in dry run mode.
+//------------------------------------------------------------------------------
+fn print_replace_plan
+(
+    config: &SshConfig,
+    options: &ReplaceOptions,
+    local_files: &BTreeMap<String, SourceFile>,
+    sync_plan: Option<&SyncPlan>,
+    preserve_plan: Option<&PreservePlan>,
+    remote_path: &str,
+    remote_target_path: &str,
+)
+{
+    println!("{}", "Dry run. No changes will be made.".yellow());
+
+    //  Extracts the archive into the staging directory.
+    let target_dir = get_target_dir(local_files, &options.target_path);
+    let remote_target_dir = join_path(remote_path, &target_dir);
+    if let Some(format) = options.archive
+    {
+        let staging_path = get_staging_path(&remote_target_dir);
+        let archive_path = get_remote_archive_path(&staging_path, format);
+        let archive = format!("archive of {} files", local_files.len());
+        print_dry_run_command("mkdir", &["-p", &staging_path]);
+        print_dry_run_transfer("Upload", &archive, &archive_path);
+        let args = format.extract_args(&archive_path, &staging_path);
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        print_dry_run_command(format.extractor(), &args);
+        print_dry_run_command("rm", &["-r", &archive_path]);
+    }
+    print_hooks_plan
+    (
+        &config.pre_deploy(),
+        &config.git_path(),
+        &config.remote_path(),
+    );
+
+    //  Prepares the remote directory.
+    let print_remove_target = ||
+    {
+        match preserve_plan
+        {
+            Some(plan) =>
+            {
+                for relative_path in &plan.removed
+                {
+                    let path = join_path(remote_path, relative_path);
+                    print_dry_run_command("rm", &["-r", &path]);
+                }
+            },
+            None => print_dry_run_command("rm", &["-r", remote_target_path]),
+        }
+    };
+    if config.release_mode()
+    {
+        if options.target_path.len() > 0 || options.delta
+        {
+            let current = config.remote_path() + "/.";
+            print_dry_run_command("mkdir", &["-p", remote_path]);
+            print_dry_run_command("cp", &["-a", &current, remote_path]);
+            if options.delta == false
+            {
+                print_remove_target();
+            }
+        }
+        else if let Some(plan) = preserve_plan
+        {
+            for relative_path in &plan.preserved
+            {
+                let from = join_path(&config.remote_path(), relative_path);
+                let to = join_path(remote_path, relative_path);
+                print_dry_run_command("mkdir", &["-p", &get_parent_path(&to)]);
+                print_dry_run_command("cp", &["-a", &from, &to]);
+            }
+        }
+    }
+    else if options.delta == false
+    {
+        print_remove_target();
+    }
+
+    //  Uploads files.
+    if let Some(plan) = sync_plan
+    {
+        for relative_path in plan.added.iter().chain(plan.changed.iter())
+        {
+            let local_file = local_files.get(relative_path).unwrap();
+            print_dry_run_transfer
+            (
+                "Upload",
+                &local_file.display(relative_path),
+                &(remote_path.to_string() + "/" + relative_path),
+            );
+        }
+        for relative_path in plan.deleted.iter().chain(plan.deleted_dirs.iter())
+        {
+            let path = remote_path.to_string() + "/" + relative_path;
+            print_dry_run_command("rm", &[&path]);
+        }
+    }
+    else if options.archive.is_some()
+    {
+        //  The extracted files are moved instead of being uploaded one by one.
+        let staging_path = get_staging_path(&remote_target_dir);
+        let from = staging_path.clone() + "/.";
+        print_dry_run_command("cp", &["-a", &from, &remote_target_dir]);
+        print_dry_run_command("rm", &["-r", &staging_path]);
+    }
+    else
+    {
+        for (relative_path, local_file) in local_files
+        {
+            print_dry_run_transfer
+            (
+                "Upload",
+                &local_file.display(relative_path),
+                &(remote_path.to_string() + "/" + relative_path),
+            );
+        }
+    }
+
+    print_dry_run_transfer
+    (
+        "Write",
+        "deploy manifest",
+        &(remote_path.to_string() + "/" + MANIFEST_FILE),
+    );
+    print_permissions_plan(&config.permissions(), remote_path);
+
+    //  Switches the release.
+    if config.release_mode()
+    {
+        let remote_link = config.remote_path();
+        let temp_link = remote_link.clone() + ".resm_tmp";
+        print_dry_run_command("ln", &["-sfn", remote_path, &temp_link]);
+        print_dry_run_command("mv", &["-Tf", &temp_link, &remote_link]);
+        println!
+        (
+            "{} : keep the latest {} releases in {}",
+            "Prune  ".yellow(),
+            config.keep_releases(),
+            config.releases_path(),
+        );
+    }
+
+    print_hooks_plan
+    (
+        &config.post_deploy(),
+        &config.git_path(),
+        &config.remote_path(),
+    );
+    print_clear_cache_plan(config);
+}
+
+//------------------------------------------------------------------------------
+/// Options for uploading only specified files.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct PatchOptions
+{
+    /// Path to the file that describes the files to be uploaded.
+    pub patch_file: String,
+
+    /// Revision to compute the changed files from.
+    pub from: String,
+
+    /// Revision to compute the changed files to.
+    pub to: String,
+
+    /// Number of files uploaded at the same time.
+    pub jobs: usize,
+
+    /// Whether to only print what would be done.
+    pub dry_run: bool,
+}
+
 //------------------------------------------------------------------------------
 /// Uploads only specified files in the local repository to the remote server.
 //------------------------------------------------------------------------------
@@ -163,54 +1046,67 @@ pub async fn upload_patch
 (
     project: &str,
     config: &SshConfig,
-    patch_file: String,
+    options: &PatchOptions,
 )
 {
-    let git_src_path = config.git_src_path();
     let remote_path = config.remote_path();
-    let remote_cache_path = config.remote_cache_path();
+    if let Err(e) = check_remote_path(&remote_path)
+    {
+        print_refused(config, &e);
+        return;
+    }
 
-    let mut paths = Vec::new();
     let mut branch_name = None;
-    if patch_file.len() > 0
+    let mut revision = None;
+    let mut to_commit_id = None;
+    let changes = if options.patch_file.len() > 0
+    {
+        get_patch_file_changes(config, &options.patch_file)
+    }
+    else if options.from.len() > 0
     {
-        //  Reads the patch file.
-        let patch_file = std::fs::read_to_string(patch_file).unwrap();
-        for line in patch_file.lines()
+        //  Gets the files changed between the revisions.
+        match get_revision_range_changes(config, &options.from, &options.to)
         {
-            let path = git_src_path.clone() + "/" + line;
-            paths.push(PathBuf::from(path));
+            Ok((from_id, to_id, changes)) =>
+            {
+                revision = Some(format!
+                (
+                    "{}..{} ({}..{})",
+                    &options.from,
+                    &options.to,
+                    &from_id[..7],
+                    &to_id[..7],
+                ));
+                to_commit_id = Some(to_id);
+                changes
+            },
+            Err(e) =>
+            {
+                println!("{} : {}", "Error".red(), e.message());
+                return;
+            },
         }
     }
     else
     {
         //  Gets the list of files from the git repository.
-        let git_path = config.git_path();
-        let git_relative_path = config.get_git_relative_path();
-        let repo = Repository::open(&git_path).unwrap();
-        let mut status_opts = StatusOptions::new();
-        status_opts.include_untracked(true);
-        let statuses = repo.statuses(Some(&mut status_opts)).unwrap();
-
-        for status in statuses.iter()
+        match get_working_tree_changes(config)
         {
-            let path = status.path().unwrap();
-            if path.starts_with(&git_relative_path) == false
+            Ok((name, changes)) =>
             {
-                continue;
-            }
-            paths.push(PathBuf::from(&(git_path.clone() + "/" + path)));
+                branch_name = name;
+                changes
+            },
+            Err(e) =>
+            {
+                println!("{} : {}", "Error".red(), e.message());
+                return;
+            },
         }
+    };
 
-        let head = repo.head().unwrap();
-        branch_name = match head.shorthand()
-        {
-            Some(name) => Some(name.to_string()),
-            None => None,
-        };
-    }
-
-    if paths.len() == 0
+    if changes.is_empty()
     {
         println!("No files to upload.");
         return;
@@ -224,108 +1120,576 @@ pub async fn upload_patch
     {
         println!("{} : {}", "Branch name".green(), &branch_name);
     }
-    println!("{} :",    "Files      ".green());
-    for path in &paths
+    if let Some(revision) = revision
     {
-        println!("    - {}", path.to_str().unwrap());
+        println!("{} : {}", "Revision   ".green(), &revision);
     }
+    changes.print();
     print_sep();
+
+    if options.dry_run
+    {
+        println!("{}", "Dry run. No changes will be made.".yellow());
+        print_hooks_plan
+        (
+            &config.pre_deploy(),
+            &config.git_path(),
+            &remote_path,
+        );
+        for (relative_path, (old_path, _)) in &changes.renamed
+        {
+            print_dry_run_command
+            (
+                "mv",
+                &[
+                    &(remote_path.clone() + "/" + old_path),
+                    &(remote_path.clone() + "/" + relative_path),
+                ],
+            );
+        }
+        for (relative_path, file) in changes.uploads()
+        {
+            print_dry_run_transfer
+            (
+                "Upload",
+                &file.display(relative_path),
+                &(remote_path.clone() + "/" + relative_path),
+            );
+        }
+        for relative_path in &changes.deleted
+        {
+            let path = remote_path.clone() + "/" + relative_path;
+            print_dry_run_command("rm", &[&path]);
+        }
+        print_dry_run_transfer
+        (
+            "Write",
+            "deploy manifest",
+            &(remote_path.clone() + "/" + MANIFEST_FILE),
+        );
+        print_permissions_plan(&config.permissions(), &remote_path);
+        print_hooks_plan
+        (
+            &config.post_deploy(),
+            &config.git_path(),
+            &remote_path,
+        );
+        print_clear_cache_plan(config);
+        return;
+    }
+
     if confirm("Are you sure you want to upload these files?") == false
     {
         println!("Canceled.");
         return;
     }
 
-    //  Uploads all files.
+    let session = get_session(project).await;
+    if acquire_lock(&session, config, "patch").await == false
+    {
+        session.close().await.unwrap();
+        return;
+    }
+
+    if run_hooks
+    (
+        &session,
+        "pre_deploy",
+        &config.pre_deploy(),
+        &config.git_path(),
+        &remote_path,
+    ).await == false
+    {
+        println!("Aborted.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
+
+    //  Reflects the changes on the remote server.
     let sftp = get_sftp_session(project).await;
     {
         let mut fs = sftp.fs();
-        for path in paths
+        let open_options = sftp.options();
+        let mut dirs = HashSet::new();
+
+        //  Moves renamed files first so that they are overwritten with the new
+        //  content below. When a file cannot be moved, the old path is removed
+        //  instead, since the new path is uploaded anyway.
+        for (relative_path, (old_path, _)) in &changes.renamed
+        {
+            let from = remote_path.clone() + "/" + old_path;
+            let to = remote_path.clone() + "/" + relative_path;
+            println!("{} : {} => {}", "Moving".green(), &from, &to);
+            mkdir_all(&mut fs, Path::new(&to).parent().unwrap(), &mut dirs)
+                .await;
+            if let Err(e) = fs.rename(&from, &to).await
+            {
+                println!
+                (
+                    "{} : Failed to move {} to {}: {}",
+                    "Error".red(),
+                    &from,
+                    &to,
+                    e,
+                );
+                remove_remote_file(&mut fs, config, &from).await;
+            }
+        }
+
+        let failed = upload_sources
+        (
+            &mut fs,
+            &open_options,
+            &remote_path,
+            changes.uploads(),
+            &mut dirs,
+            options.jobs,
+        ).await;
+
+        for relative_path in &changes.deleted
+        {
+            let path = remote_path.clone() + "/" + relative_path;
+            remove_remote_file(&mut fs, config, &path).await;
+        }
+
+        //  Updates the records of the changed files in the deploy manifest.
+        let mut manifest = DeployManifest::new(config, "patch");
+        if let Some(to_id) = &to_commit_id
         {
-            let path_str = path.to_str().unwrap();
-            let remote_path_str = remote_path.clone()
-                + &path_str.replace(&git_src_path, "");
-            upload(&mut fs, path_str, &remote_path_str, path.is_dir()).await;
+            manifest.commit = Some(to_id.clone());
+            manifest.branch = Some(options.to.clone());
+            manifest.dirty = false;
         }
+        if let Some(previous) = read_manifest(&mut fs, &remote_path).await
+        {
+            manifest.files = previous.files;
+        }
+        for (old_path, _) in changes.renamed.values()
+        {
+            manifest.files.remove(old_path);
+        }
+        for relative_path in &changes.deleted
+        {
+            manifest.files.remove(relative_path);
+        }
+        record_files(&mut manifest, changes.uploads(), &failed);
+        write_manifest(&mut fs, &remote_path, &manifest).await;
     }
     sftp.close().await.unwrap();
 
+    if apply_permissions(&session, &config.permissions(), &remote_path).await
+        == false
+    {
+        println!("Aborted.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
+
+    if run_hooks
+    (
+        &session,
+        "post_deploy",
+        &config.post_deploy(),
+        &config.git_path(),
+        &remote_path,
+    ).await == false
+    {
+        println!("Aborted.");
+        release_lock(&session, config).await;
+        session.close().await.unwrap();
+        return;
+    }
+
     println!("Done.\n");
-    let session = get_session(project).await;
-    clear_cache(&session, &remote_cache_path).await;
+    clear_cache(&session, config).await;
+    release_lock(&session, config).await;
     session.close().await.unwrap();
     println!("Done.");
 }
 
 //------------------------------------------------------------------------------
-/// Uploads file to the remote server.
+/// Uploads the archive into the staging directory and extracts it there.
+/// Returns false after removing the staging directory if either failed.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `project` - Project name.
+/// - `config` - SSH configuration.
+/// - `format` - Format of the archive.
+/// - `archive_file` - Local archive file.
+/// - `staging_path` - Remote staging directory.
 //------------------------------------------------------------------------------
-async fn upload( fs: &mut Fs, from: &str, to: &str, is_dir: bool )
+async fn stage_archive
+(
+    session: &Session,
+    project: &str,
+    config: &SshConfig,
+    format: ArchiveFormat,
+    archive_file: &NamedTempFile,
+    staging_path: &str,
+) -> bool
 {
-    println!
+    //  Removes the staging directory left by an interrupted deploy.
+    remove_remote(session, config, staging_path).await;
+    session
+        .command("mkdir")
+        .args(["-p", staging_path])
+        .output()
+        .await
+        .unwrap();
+
+    let remote_archive = get_remote_archive_path(staging_path, format);
+    let archive_size = archive_file.as_file().metadata().unwrap().len();
+    let sftp = get_sftp_session(project).await;
+    let progress = Progress::new("Uploading", 1, archive_size);
+    progress.println(&format!
     (
         "{} : {} => {}",
         "Uploading".green(),
-        from,
-        to,
+        archive_file.path().display(),
+        &remote_archive,
+    ));
+    let uploaded = upload_file
+    (
+        &sftp.options(),
+        archive_file.path(),
+        &remote_archive,
+        &progress,
+    ).await;
+    progress.inc_file();
+    progress.finish();
+    sftp.close().await.unwrap();
+
+    let extracted = match uploaded
+    {
+        Ok(_) =>
+        {
+            extract_archive(session, format, &remote_archive, staging_path)
+                .await
+        },
+        Err(e) =>
+        {
+            println!
+            (
+                "{} : Failed to upload the archive: {}",
+                "Error".red(),
+                e,
+            );
+            false
+        },
+    };
+    remove_remote(session, config, &remote_archive).await;
+    if extracted == false
+    {
+        remove_remote(session, config, staging_path).await;
+    }
+    extracted
+}
+
+//------------------------------------------------------------------------------
+/// Copies the extracted files from the staging directory into the target
+/// directory, and removes the staging directory. Returns false if the files
+/// could not be copied.
+///
+/// # Arguments
+///
+/// - `session` - SSH session.
+/// - `config` - SSH configuration.
+/// - `staging_path` - Remote staging directory.
+/// - `remote_target_path` - Remote directory that the files are deployed to.
+//------------------------------------------------------------------------------
+async fn swap_staging
+(
+    session: &Session,
+    config: &SshConfig,
+    staging_path: &str,
+    remote_target_path: &str,
+) -> bool
+{
+    println!
+    (
+        "{} : {} => {}",
+        "Copying".green(),
+        staging_path,
+        remote_target_path,
     );
+    let output = session
+        .command("cp")
+        .args(["-a", &(staging_path.to_string() + "/."), remote_target_path])
+        .output()
+        .await
+        .unwrap();
+    if output.status.success() == false
+    {
+        println!
+        (
+            "{} : Failed to copy {} into {}: {}",
+            "Error".red(),
+            staging_path,
+            remote_target_path,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+        return false;
+    }
+    remove_remote(session, config, staging_path).await;
+    true
+}
 
-    if is_dir
+//------------------------------------------------------------------------------
+/// Uploads the source files to the remote server concurrently.
+///
+/// The parent directories are created first, and then up to `jobs` files are
+/// written at the same time over the SFTP session while the progress is shown.
+/// Files that cannot be read are reported and skipped. Returns the relative
+/// paths of the files that were not uploaded.
+///
+/// # Arguments
+///
+/// - `fs` - SFTP file system.
+/// - `options` - SFTP open options.
+/// - `remote_path` - Remote path that the files are uploaded under.
+/// - `files` - Source files keyed by the path relative to the remote path.
+/// - `dirs` - Remote directories known to exist.
+/// - `jobs` - Number of files uploaded at the same time.
+//------------------------------------------------------------------------------
+async fn upload_sources
+(
+    fs: &mut Fs,
+    options: &OpenOptions,
+    remote_path: &str,
+    files: Vec<(&String, &SourceFile)>,
+    dirs: &mut HashSet<PathBuf>,
+    jobs: usize,
+) -> BTreeSet<String>
+{
+    //  Files that no longer exist or cannot be read are reported and skipped.
+    let mut failed = BTreeSet::new();
+    let mut uploads = Vec::new();
+    let mut total_bytes = 0;
+    for (relative_path, file) in files
     {
-        fs.create_dir(to).await.unwrap();
+        match file.len()
+        {
+            Ok(len) =>
+            {
+                let to = remote_path.to_string() + "/" + relative_path;
+                uploads.push((relative_path, file, to));
+                total_bytes += len;
+            },
+            Err(e) =>
+            {
+                println!
+                (
+                    "{} : Failed to read {}: {}",
+                    "Error".red(),
+                    file.display(relative_path),
+                    e,
+                );
+                failed.insert(relative_path.clone());
+            },
+        }
     }
-    else
+    for (_, _, to) in &uploads
     {
-        if fs.metadata(Path::new(to).parent().unwrap()).await.is_err()
+        mkdir_all(fs, Path::new(to).parent().unwrap(), dirs).await;
+    }
+
+    let total_files = uploads.len() as u64;
+    let progress = Progress::new("Uploading", total_files, total_bytes);
+    let progress = &progress;
+    let results: Vec<_> = stream::iter(uploads)
+        .map(|(relative_path, file, to)|
+        {
+            async move
+            {
+                let uploaded = upload_source
+                (
+                    options,
+                    relative_path,
+                    file,
+                    &to,
+                    progress,
+                ).await;
+                progress.inc_file();
+                (relative_path, uploaded)
+            }
+        })
+        .buffer_unordered(jobs.max(1))
+        .collect()
+        .await;
+    progress.finish();
+    for (relative_path, uploaded) in results
+    {
+        if uploaded == false
         {
-            mkdir_all(fs, &Path::new(to).parent().unwrap()).await;
+            failed.insert(relative_path.clone());
         }
+    }
+    failed
+}
+
+//------------------------------------------------------------------------------
+/// Uploads the source file to the remote server. The parent directory must
+/// exist. Returns false after reporting the error if the upload failed.
+//------------------------------------------------------------------------------
+async fn upload_source
+(
+    options: &OpenOptions,
+    relative_path: &str,
+    file: &SourceFile,
+    to: &str,
+    progress: &Progress,
+) -> bool
+{
+    let from = file.display(relative_path);
+    progress.println(&format!("{} : {} => {}", "Uploading".green(), from, to));
+    let result = match file.open()
+    {
+        Ok(mut reader) =>
+        {
+            upload_reader(options, &mut reader, file.mode(), to, progress).await
+        },
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result
+    {
+        progress.println(&format!
+        (
+            "{} : Failed to upload {}: {}",
+            "Error".red(),
+            from,
+            e,
+        ));
+        return false;
+    }
+    true
+}
 
-        if let Ok(mut file) = File::open(from)
+//------------------------------------------------------------------------------
+/// Records the hashes of the files in the deploy manifest. Files that were not
+/// uploaded lose their records, and a file whose hash cannot be read is
+/// reported and left out.
+///
+/// # Arguments
+///
+/// - `manifest` - Deploy manifest to update.
+/// - `files` - Source files keyed by the path relative to the remote path.
+/// - `failed` - Relative paths of the files that were not uploaded.
+//------------------------------------------------------------------------------
+fn record_files<'a, I>
+(
+    manifest: &mut DeployManifest,
+    files: I,
+    failed: &BTreeSet<String>,
+)
+where
+    I: IntoIterator<Item = (&'a String, &'a SourceFile)>,
+{
+    for (relative_path, file) in files
+    {
+        manifest.files.remove(relative_path);
+        if failed.contains(relative_path)
         {
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf).unwrap();
-            fs.write(to, &buf).await.unwrap();
+            continue;
         }
-        else
+        match file.hash()
         {
-            println!("Skip to upload the file.");
+            Ok(hash) =>
+            {
+                manifest.files.insert(relative_path.clone(), hash);
+            },
+            Err(e) =>
+            {
+                println!
+                (
+                    "{} : Failed to read {}, which is not recorded in the \
+                    deploy manifest: {}",
+                    "Warning".yellow(),
+                    file.display(relative_path),
+                    e,
+                );
+            },
         }
     }
 }
 
 //------------------------------------------------------------------------------
-/// Creates all directories in the path.
+/// Creates all directories in the path. Directories that are known to exist
+/// are recorded in `dirs` so that they are not checked again.
 //------------------------------------------------------------------------------
 #[async_recursion]
-async fn mkdir_all( fs: &mut Fs, path: &Path )
+async fn mkdir_all( fs: &mut Fs, path: &Path, dirs: &mut HashSet<PathBuf> )
 {
-    if fs.metadata(path.parent().unwrap()).await.is_err()
+    if dirs.contains(path)
     {
-        mkdir_all(fs, path.parent().unwrap()).await;
+        return;
     }
-    fs.create_dir(path).await.unwrap();
+    if fs.metadata(path).await.is_err()
+    {
+        if let Some(parent) = path.parent()
+        {
+            mkdir_all(fs, parent, dirs).await;
+        }
+        fs.create_dir(path).await.unwrap();
+    }
+    dirs.insert(path.to_path_buf());
+}
+
+//------------------------------------------------------------------------------
+/// Prints what `clear_cache` would do in dry run mode.
+//------------------------------------------------------------------------------
+pub fn print_clear_cache_plan( config: &SshConfig )
+{
+    let remote_cache_path = config.remote_cache_path();
+    print_sep();
+    println!("{} : {}", "Remote cache path".green(), &remote_cache_path);
+    print_sep();
+    if let Err(e) = check_removal(config, &remote_cache_path)
+    {
+        print_refused(config, &e);
+        return;
+    }
+    if let Err(e) = check_mode(&config.remote_cache_mode())
+    {
+        print_refused(config, &("remote_cache_mode: ".to_string() + &e));
+        return;
+    }
+    print_dry_run_command("rm", &["-r", &remote_cache_path]);
+    print_dry_run_command
+    (
+        "mkdir",
+        &[&remote_cache_path, "-m", &config.remote_cache_mode()],
+    );
 }
 
 //------------------------------------------------------------------------------
 /// Clears the cache on the remote server.
 //------------------------------------------------------------------------------
-pub async fn clear_cache( session: &Session, remote_cache_path: &str )
+pub async fn clear_cache( session: &Session, config: &SshConfig )
 {
+    let remote_cache_path = config.remote_cache_path();
     print_sep();
-    println!("{} : {}", "Remote cache path".green(), remote_cache_path);
+    println!("{} : {}", "Remote cache path".green(), &remote_cache_path);
     print_sep();
+    if let Err(e) = check_removal(config, &remote_cache_path)
+    {
+        print_refused(config, &e);
+        return;
+    }
+    if let Err(e) = check_mode(&config.remote_cache_mode())
+    {
+        print_refused(config, &("remote_cache_mode: ".to_string() + &e));
+        return;
+    }
     if confirm("Delete the cache for the above path?")
     {
-        session
-            .command("rm")
-            .args(["-r", remote_cache_path])
-            .output()
-            .await
-            .unwrap();
+        remove_remote(session, config, &remote_cache_path).await;
         session
             .command("mkdir")
-            .args([remote_cache_path, "-m", "777"])
+            .args([&remote_cache_path, "-m", &config.remote_cache_mode()])
             .output()
             .await
             .unwrap();
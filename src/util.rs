@@ -1,55 +1,222 @@
 //! Utility functions.
 
 use crate::ssh_config::SshConfig;
+use crate::inherit::{
+    RawEntry,
+    Origin,
+    get_entry_key,
+    resolve_entries,
+    find_origin,
+};
+use crate::interpolate::Interpolator;
+use crate::config_format::{ CONFIG_EXTENSIONS, parse_entries };
+use crate::validate::{ Diagnostics, check_configs, check_unknown_fields };
 
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
 
 use glob::glob;
+use ignore::WalkBuilder;
+use ignore::gitignore::{ Gitignore, GitignoreBuilder };
+use ignore::overrides::{ Override, OverrideBuilder };
 use colored::Colorize;
 use openssh::{ Session, SessionBuilder };
 use openssh_sftp_client::{ Sftp, SftpOptions };
 use chrono::Local;
 
 //------------------------------------------------------------------------------
-/// Loads JSON files.
+/// Loads the configuration files (JSON, TOML and YAML), resolves the `extends`
+/// field of the entries, and then the environment variables and secret
+/// references in their values. The problems found are printed, and the entries
+/// that cannot be loaded are skipped.
 //------------------------------------------------------------------------------
 pub fn load_json( path: &str ) -> BTreeMap<String, SshConfig>
 {
-    //  Gets the path to JSON files.
-    let json_path = path.trim_end_matches("/").to_string() + "/**/*.json";
+    let (config_entries, diagnostics) = load_config(path);
+    diagnostics.print();
+    config_entries
+}
 
-    //  Loads JSON files.
-    let mut config_entries: Vec<SshConfig> = Vec::new();
-    for entry in glob(&json_path).unwrap()
+//------------------------------------------------------------------------------
+/// Loads the configuration files and validates the entries.
+///
+/// # Arguments
+///
+/// - `path` - Directory that the configuration files are searched in.
+//------------------------------------------------------------------------------
+pub fn load_config
+(
+    path: &str,
+) -> (BTreeMap<String, SshConfig>, Diagnostics)
+{
+    let mut diagnostics = Diagnostics::default();
+    let mut raw_entries: Vec<RawEntry> = Vec::new();
+    for extension in CONFIG_EXTENSIONS
     {
-        match entry
+        //  Gets the path to the configuration files.
+        let config_path = path.trim_end_matches("/").to_string()
+            + "/**/*."
+            + extension;
+
+        //  Loads the configuration files.
+        for entry in glob(&config_path).unwrap()
         {
-            Ok(path) =>
+            match entry
             {
-                let json_data = std::fs::read_to_string(path).unwrap();
-                match serde_json::from_str::<Vec<SshConfig>>(&json_data)
+                Ok(path) =>
                 {
-                    Ok(config_entry) => config_entries.extend(config_entry),
-                    Err(e) => println!("{:?}", e),
-                }
+                    let file = path.to_str().unwrap().to_string();
+                    let content = match std::fs::read_to_string(&path)
+                    {
+                        Ok(content) => content,
+                        Err(e) =>
+                        {
+                            println!
+                            (
+                                "{} : Failed to read {}: {}",
+                                "Error".red(),
+                                &file,
+                                e,
+                            );
+                            continue;
+                        },
+                    };
+                    match parse_entries(&path, &content)
+                    {
+                        Ok(values) =>
+                        {
+                            raw_entries.extend
+                            (
+                                values
+                                    .into_iter()
+                                    .enumerate()
+                                    .map(|(index, value)| RawEntry
+                                    {
+                                        value,
+                                        file: file.clone(),
+                                        index,
+                                    }),
+                            );
+                        },
+                        Err(e) =>
+                        {
+                            diagnostics.add_parse_error
+                            (
+                                &file,
+                                e.line,
+                                &e.message,
+                            );
+                        },
+                    }
+                    diagnostics.add_file(&file, content);
+                },
+                Err(e) => println!("{} : {}", "Error".red(), e),
+            }
+        }
+    }
+
+    let mut interpolator = Interpolator::default();
+    let mut config_entries: BTreeMap<String, SshConfig> = BTreeMap::new();
+    let resolved_entries = resolve_entries(&raw_entries);
+    for (raw, resolved) in raw_entries.iter().zip(resolved_entries)
+    {
+        let key = get_entry_key(&raw.value);
+        let location = Origin::of(raw);
+        let mut resolved = match resolved
+        {
+            Ok(resolved) => resolved,
+            Err(e) =>
+            {
+                diagnostics.add(&key, &location, "extends", &e);
+                continue;
             },
-            Err(e) => println!("{:?}", e),
+        };
+
+        //  The environment can be inherited.
+        let key = get_entry_key(&resolved.value);
+
+        if let Err(e) =
+            interpolator.resolve(&mut resolved.value, &resolved.origins)
+        {
+            let origin = find_origin(&resolved.origins, &e.field)
+                .unwrap_or(&location);
+            diagnostics.add(&key, origin, &e.field, &e.message);
+            continue;
+        }
+
+        check_unknown_fields
+        (
+            &mut diagnostics,
+            &key,
+            &resolved.value,
+            &resolved.origins,
+            &location,
+        );
+        let mut entry: SshConfig =
+            match serde_path_to_error::deserialize(resolved.value)
+            {
+                Ok(entry) => entry,
+                Err(e) =>
+                {
+                    let field = e.path().to_string().replace("?", "");
+                    let field = field.trim_start_matches('.');
+                    let origin = find_origin(&resolved.origins, field)
+                        .unwrap_or(&location);
+                    let message = e.inner().to_string();
+                    diagnostics.add(&key, origin, field, &message);
+                    continue;
+                },
+            };
+        entry.set_origins(resolved.origins);
+        entry.set_location(location.clone());
+
+        //  The last entry with the same key takes precedence.
+        if let Some(previous) = config_entries.get(&key)
+        {
+            let message = format!
+            (
+                "duplicate key, overrides the entry at {}",
+                diagnostics.locate(previous.location().unwrap()),
+            );
+            diagnostics.add(&key, &location, "", &message);
         }
+        config_entries.insert(key, entry);
     }
 
-    config_entries
-        .into_iter()
-        .map(|entry|
+    check_configs(&mut diagnostics, &config_entries);
+    (config_entries, diagnostics)
+}
+
+//------------------------------------------------------------------------------
+/// Gets the configurations that match the specified project.
+///
+/// If there is no exact match, all projects whose keys start with the
+/// specified name are returned.
+//------------------------------------------------------------------------------
+pub fn get_matched_configs<'a>
+(
+    config_entries: &'a BTreeMap<String, SshConfig>,
+    project: &str,
+) -> Vec<(&'a String, &'a SshConfig)>
+{
+    let configs: Vec<(&String, &SshConfig)> =
+        match config_entries.get_key_value(project)
         {
-            let mut key = entry.project();
-            if entry.environment().len() > 0
+            Some(entry) => vec![entry],
+            None =>
             {
-                key += &("_".to_string() + entry.environment().as_str());
-            }
-            (key, entry)
-        })
-        .collect()
+                config_entries
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(project))
+                    .collect()
+            },
+        };
+
+    if configs.len() == 0
+    {
+        println!("Project not found.");
+    }
+    configs
 }
 
 //------------------------------------------------------------------------------
@@ -71,27 +238,224 @@ pub async fn get_sftp_session( project: &str ) -> Sftp
 
 //------------------------------------------------------------------------------
 /// Gets file paths in the specified directory.
+///
+/// Files ignored by `.gitignore`, `.git/info/exclude` (only if `git_ignore` is
+/// true) or `.resmignore`, and files matching the `exclude` globs are skipped.
+/// Returns the error if an `exclude` glob is invalid.
+///
+/// # Arguments
+///
+/// - `dir` - Directory to walk.
+/// - `excludes` - Globs of files to be excluded.
+/// - `git_ignore` - Whether to apply the git ignore rules.
 //------------------------------------------------------------------------------
-pub fn get_file_paths( dir: &str ) -> Vec<PathBuf>
+pub fn get_file_paths
+(
+    dir: &str,
+    excludes: &[String],
+    git_ignore: bool,
+) -> Result<Vec<PathBuf>, String>
 {
     let mut file_paths: Vec<PathBuf> = Vec::new();
-    println!("{}", dir);
-    for entry in glob(&(dir.to_string() + "/**/*")).unwrap()
+    let walker = WalkBuilder::new(dir)
+        .hidden(false)
+        .git_global(false)
+        .git_ignore(git_ignore)
+        .git_exclude(git_ignore)
+        .require_git(false)
+        .add_custom_ignore_filename(".resmignore")
+        .overrides(get_exclude_matcher(dir, excludes)?)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+    for entry in walker
     {
         match entry
         {
-            Ok(path) =>
+            Ok(entry) =>
             {
+                let path = entry.into_path();
                 if path.is_dir()
                 {
                     continue;
                 }
                 file_paths.push(path);
             },
-            Err(e) => println!("{:?}", e),
+            Err(e) => println!("{} : {}", "Error".red(), e),
         }
     }
-    file_paths
+    file_paths.sort();
+    Ok(file_paths)
+}
+
+//------------------------------------------------------------------------------
+/// Ignore files read by `get_file_paths`, used to find the paths that it skips
+/// without walking them.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct IgnoreRules
+{
+    dir: PathBuf,
+
+    /// Matchers of the ignore files, the deepest directory first.
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreRules
+{
+    //--------------------------------------------------------------------------
+    /// Reads the ignore files that apply to the directory: `.gitignore` and
+    /// `.git/info/exclude` (only if `git_ignore` is true) and `.resmignore`,
+    /// in the directory, its parents and its sub directories.
+    ///
+    /// # Arguments
+    ///
+    /// - `dir` - Directory that `get_file_paths` walks.
+    /// - `git_ignore` - Whether to apply the git ignore rules.
+    //--------------------------------------------------------------------------
+    pub fn new( dir: &str, git_ignore: bool ) -> Self
+    {
+        let mut filenames = vec![".resmignore"];
+        if git_ignore
+        {
+            filenames.push(".gitignore");
+        }
+
+        let mut dirs: Vec<PathBuf> = Path::new(dir)
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.to_path_buf())
+            .collect();
+        let walker = WalkBuilder::new(dir)
+            .hidden(false)
+            .git_global(false)
+            .git_ignore(git_ignore)
+            .git_exclude(git_ignore)
+            .require_git(false)
+            .add_custom_ignore_filename(".resmignore")
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+        for entry in walker.flatten()
+        {
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false)
+            {
+                dirs.push(entry.into_path());
+            }
+        }
+
+        let mut matchers = Vec::new();
+        for dir in &dirs
+        {
+            let mut files: Vec<PathBuf> = filenames
+                .iter()
+                .map(|filename| dir.join(filename))
+                .collect();
+            if git_ignore
+            {
+                files.push(dir.join(".git/info/exclude"));
+            }
+            for file in files.into_iter().filter(|file| file.is_file())
+            {
+                let mut builder = GitignoreBuilder::new(dir);
+                builder.add(&file);
+                if let Ok(matcher) = builder.build()
+                {
+                    matchers.push(matcher);
+                }
+            }
+        }
+        matchers.sort_by_key(|matcher|
+        {
+            std::cmp::Reverse(matcher.path().components().count())
+        });
+
+        Self { dir: PathBuf::from(dir), matchers }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Checks whether the path or any of its parent directories is ignored.
+    /// The deepest ignore file that matches the path decides.
+    ///
+    /// # Arguments
+    ///
+    /// - `relative_path` - Path relative to the directory.
+    /// - `is_dir` - Whether the path is a directory.
+    //--------------------------------------------------------------------------
+    pub fn is_ignored( &self, relative_path: &str, is_dir: bool ) -> bool
+    {
+        let path = self.dir.join(relative_path);
+        for matcher in &self.matchers
+        {
+            if path.starts_with(matcher.path()) == false
+            {
+                continue;
+            }
+            let matched = matcher.matched_path_or_any_parents(&path, is_dir);
+            if matched.is_none() == false
+            {
+                return matched.is_ignore();
+            }
+        }
+        false
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Gets the matcher for the `exclude` globs. Returns the error if a glob is
+/// invalid.
+///
+/// # Arguments
+///
+/// - `dir` - Directory that the globs are relative to.
+/// - `excludes` - Globs of files to be excluded.
+//------------------------------------------------------------------------------
+pub fn get_exclude_matcher
+(
+    dir: &str,
+    excludes: &[String],
+) -> Result<Override, String>
+{
+    let mut builder = OverrideBuilder::new(dir);
+    for exclude in excludes
+    {
+        builder.add(&("!".to_string() + exclude)).map_err(|e| match e
+        {
+            ignore::Error::Glob { err, .. } =>
+            {
+                format!("{} is not a valid glob: {}", exclude, err)
+            },
+            e => e.to_string(),
+        })?;
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+//------------------------------------------------------------------------------
+/// Checks whether the relative path or any of its parent directories is
+/// excluded by the matcher.
+///
+/// # Arguments
+///
+/// - `matcher` - Matcher for the `exclude` globs.
+/// - `path` - Relative path to check.
+/// - `is_dir` - Whether the path is a directory.
+//------------------------------------------------------------------------------
+pub fn is_excluded( matcher: &Override, path: &str, is_dir: bool ) -> bool
+{
+    if matcher.matched(path, is_dir).is_ignore()
+    {
+        return true;
+    }
+
+    let mut dir = path;
+    while let Some(pos) = dir.rfind('/')
+    {
+        dir = &dir[..pos];
+        if matcher.matched(dir, true).is_ignore()
+        {
+            return true;
+        }
+    }
+    false
 }
 
 //------------------------------------------------------------------------------
@@ -102,6 +466,30 @@ pub fn print_sep()
     println!("{}", "=".to_string().repeat(80));
 }
 
+//------------------------------------------------------------------------------
+/// Prints the command that would be run on the remote server in dry run mode.
+//------------------------------------------------------------------------------
+pub fn print_dry_run_command( program: &str, args: &[&str] )
+{
+    println!("{} : {} {}", "Command".yellow(), program, args.join(" "));
+}
+
+//------------------------------------------------------------------------------
+/// Prints the file that would be transferred in dry run mode.
+//------------------------------------------------------------------------------
+pub fn print_dry_run_transfer( label: &str, from: &str, to: &str )
+{
+    println!("{} : {} => {}", label.yellow(), from, to);
+}
+
+//------------------------------------------------------------------------------
+/// Quotes the string for a POSIX shell.
+//------------------------------------------------------------------------------
+pub fn shell_quote( s: &str ) -> String
+{
+    "'".to_string() + &s.replace('\'', "'\\''") + "'"
+}
+
 //------------------------------------------------------------------------------
 /// Confirm.
 //------------------------------------------------------------------------------
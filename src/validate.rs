@@ -0,0 +1,630 @@
+//! Validation of the configuration entries.
+
+use crate::ssh_config::SshConfig;
+use crate::connect_info::ConnectInfo;
+use crate::inherit::{ Origin, find_origin };
+use crate::config_format::{ find_entry_lines, find_field_line };
+use crate::util::{ load_config, get_exclude_matcher };
+
+use std::collections::{ BTreeMap, HashMap };
+use std::path::Path;
+
+use colored::Colorize;
+use schemars::schema_for;
+use schemars::schema::{ RootSchema, Schema, SchemaObject, SingleOrVec };
+use serde_json::Value;
+
+//------------------------------------------------------------------------------
+/// Subcommands whose required fields can be checked.
+//------------------------------------------------------------------------------
+pub const COMMANDS: [&str; 10] =
+[
+    "replace",
+    "patch",
+    "rollback",
+    "status",
+    "diff",
+    "pull",
+    "clear",
+    "unlock",
+    "backup",
+    "backup-db",
+];
+
+//------------------------------------------------------------------------------
+/// Problem found in a configuration file.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+struct Diagnostic
+{
+    file: String,
+    line: Option<usize>,
+    entry: String,
+    field: String,
+    message: String,
+}
+
+impl Diagnostic
+{
+    //--------------------------------------------------------------------------
+    /// Prints the diagnostic as `file:line: entry: field: message`.
+    //--------------------------------------------------------------------------
+    fn print( &self )
+    {
+        let mut location = self.file.clone();
+        if let Some(line) = self.line
+        {
+            location += &(":".to_string() + &line.to_string());
+        }
+        let mut subject = String::new();
+        for part in [&self.entry, &self.field]
+        {
+            if part.len() > 0
+            {
+                subject += &(part.to_string() + ": ");
+            }
+        }
+        println!
+        (
+            "{} : {}: {}{}",
+            "Error".red(),
+            location,
+            subject,
+            self.message,
+        );
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Diagnostics of the configuration files. The contents of the files are kept
+/// to find the lines of the fields.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct Diagnostics
+{
+    diagnostics: Vec<Diagnostic>,
+    contents: HashMap<String, String>,
+}
+
+impl Diagnostics
+{
+    //--------------------------------------------------------------------------
+    /// Keeps the content of the configuration file.
+    //--------------------------------------------------------------------------
+    pub fn add_file( &mut self, file: &str, content: String )
+    {
+        self.contents.insert(file.to_string(), content);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds an error of parsing the file.
+    //--------------------------------------------------------------------------
+    pub fn add_parse_error
+    (
+        &mut self,
+        file: &str,
+        line: Option<usize>,
+        message: &str,
+    )
+    {
+        self.diagnostics.push(Diagnostic
+        {
+            file: file.to_string(),
+            line,
+            entry: "".to_string(),
+            field: "".to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds a diagnostic of the field, located in the entry that the field is
+    /// written in.
+    ///
+    /// # Arguments
+    ///
+    /// - `entry` - Key of the entry that the problem was found in.
+    /// - `origin` - Entry that the field is written in.
+    /// - `field` - Field path, or an empty string for the entry itself.
+    /// - `message` - Description of the problem.
+    //--------------------------------------------------------------------------
+    pub fn add
+    (
+        &mut self,
+        entry: &str,
+        origin: &Origin,
+        field: &str,
+        message: &str,
+    )
+    {
+        let line = self.find_line(origin, field);
+        self.diagnostics.push(Diagnostic
+        {
+            file: origin.file.clone(),
+            line,
+            entry: entry.to_string(),
+            field: field.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the location of the entry as `file:line`.
+    //--------------------------------------------------------------------------
+    pub fn locate( &self, origin: &Origin ) -> String
+    {
+        match self.find_line(origin, "")
+        {
+            Some(line) => format!("{}:{}", &origin.file, line),
+            None => origin.file.clone(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Finds the line of the field, or of the entry if the field is not found.
+    //--------------------------------------------------------------------------
+    fn find_line( &self, origin: &Origin, field: &str ) -> Option<usize>
+    {
+        let content = self.contents.get(&origin.file)?;
+        let path = Path::new(&origin.file);
+        if field.len() > 0
+        {
+            let line = find_field_line(path, content, origin.index, field);
+            if line.is_some()
+            {
+                return line;
+            }
+        }
+        find_entry_lines(path, content).get(origin.index).copied()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of the diagnostics.
+    //--------------------------------------------------------------------------
+    pub fn len( &self ) -> usize
+    {
+        self.diagnostics.len()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns true if no problem was found.
+    //--------------------------------------------------------------------------
+    pub fn is_empty( &self ) -> bool
+    {
+        self.diagnostics.is_empty()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Keeps only the diagnostics of the files and of the entries for which
+    /// the predicate returns true.
+    //--------------------------------------------------------------------------
+    pub fn retain_entries<F>( &mut self, predicate: F )
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.diagnostics.retain(|diagnostic|
+        {
+            diagnostic.entry.len() == 0 || predicate(&diagnostic.entry)
+        });
+    }
+
+    //--------------------------------------------------------------------------
+    /// Prints the diagnostics.
+    //--------------------------------------------------------------------------
+    pub fn print( &self )
+    {
+        for diagnostic in &self.diagnostics
+        {
+            diagnostic.print();
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Validates the configuration files and prints the problems found. Returns
+/// true if there is no problem.
+///
+/// # Arguments
+///
+/// - `env_path` - Directory that the configuration files are searched in.
+/// - `project` - Project to validate, or None for all projects.
+/// - `command` - Subcommand whose required fields are checked.
+//------------------------------------------------------------------------------
+pub fn validate
+(
+    env_path: &str,
+    project: Option<&str>,
+    command: Option<&str>,
+) -> bool
+{
+    let (mut config_entries, mut diagnostics) = load_config(env_path);
+    if let Some(project) = project
+    {
+        //  Same as `get_matched_configs`, an exact match takes precedence over
+        //  the keys that start with the project name.
+        let exact = config_entries.contains_key(project);
+        let matches = |key: &str|
+        {
+            key == project || (exact == false && key.starts_with(project))
+        };
+        config_entries.retain(|key, _| matches(key));
+        diagnostics.retain_entries(matches);
+    }
+    if let Some(command) = command
+    {
+        check_required_fields(&mut diagnostics, &config_entries, command);
+    }
+
+    diagnostics.print();
+    if diagnostics.is_empty()
+    {
+        println!("{}", "No problems found.".green());
+        return true;
+    }
+    println!("{} problem(s) found.", diagnostics.len());
+    false
+}
+
+//------------------------------------------------------------------------------
+/// Checks the paths, globs, permission rules and identity files of the
+/// entries.
+///
+/// # Arguments
+///
+/// - `diagnostics` - Diagnostics to add the problems to.
+/// - `config_entries` - Configuration entries keyed by `project_environment`.
+//------------------------------------------------------------------------------
+pub fn check_configs
+(
+    diagnostics: &mut Diagnostics,
+    config_entries: &BTreeMap<String, SshConfig>,
+)
+{
+    for (key, config) in config_entries
+    {
+        let paths =
+        [
+            ("remote_path", config.remote_path()),
+            ("remote_cache_path", config.remote_cache_path()),
+            ("git_path", config.git_path()),
+            ("git_src_path", config.git_src_path()),
+            ("backup_path", config.backup_path()),
+            ("artifact_path", config.artifact_path()),
+        ];
+        for (field, path) in paths
+        {
+            if path.len() > 0 && path.starts_with('/') == false
+            {
+                let message = "must be an absolute path";
+                add(diagnostics, key, config, field, message);
+            }
+        }
+
+        let globs =
+        [
+            ("exclude", config.exclude()),
+            ("preserve", config.preserve()),
+        ];
+        for (field, globs) in globs
+        {
+            for (i, glob) in globs.into_iter().enumerate()
+            {
+                if let Err(e) = get_exclude_matcher("", &[glob])
+                {
+                    let field = format!("{}[{}]", field, i);
+                    add(diagnostics, key, config, &field, &e);
+                }
+            }
+        }
+        for (i, rule) in config.permissions().iter().enumerate()
+        {
+            if let Err(e) = rule.check()
+            {
+                let field = format!("permissions[{}]", i);
+                add(diagnostics, key, config, &field, &e);
+            }
+        }
+
+        check_identity_file
+        (
+            diagnostics,
+            key,
+            config,
+            "connect_info",
+            config.connect_info(),
+        );
+        for (i, tunnel) in config.tunnels().iter().flatten().enumerate()
+        {
+            let field = format!("tunnels[{}]", i);
+            check_identity_file(diagnostics, key, config, &field, tunnel);
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the entry has no fields unknown to the JSON Schema. Unknown
+/// fields are ignored when the entry is loaded, so they are usually typos.
+///
+/// # Arguments
+///
+/// - `diagnostics` - Diagnostics to add the problems to.
+/// - `key` - Key of the entry.
+/// - `value` - Entry with the inherited values resolved.
+/// - `origins` - Origins of the values keyed by the field path.
+/// - `location` - Entry itself, used when the origin is not found.
+//------------------------------------------------------------------------------
+pub fn check_unknown_fields
+(
+    diagnostics: &mut Diagnostics,
+    key: &str,
+    value: &Value,
+    origins: &BTreeMap<String, Origin>,
+    location: &Origin,
+)
+{
+    let root = schema_for!(SshConfig);
+    let mut unknown_fields = Vec::new();
+    let schema = Schema::Object(root.schema.clone());
+    find_unknown_fields(&root, &schema, value, "", &mut unknown_fields);
+    for field in unknown_fields
+    {
+        let origin = find_origin(origins, &field).unwrap_or(location);
+        diagnostics.add(key, origin, &field, "unknown field");
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Finds the fields of the value that the schema does not define, recursing
+/// into the known objects and arrays.
+//------------------------------------------------------------------------------
+fn find_unknown_fields
+(
+    root: &RootSchema,
+    schema: &Schema,
+    value: &Value,
+    path: &str,
+    unknown_fields: &mut Vec<String>,
+)
+{
+    let mut objects = Vec::new();
+    resolve_schema(root, schema, &mut objects);
+    match value
+    {
+        Value::Object(map) =>
+        {
+            let validations: Vec<_> = objects
+                .iter()
+                .filter_map(|object| object.object.as_deref())
+                .collect();
+            let is_closed = validations.len() > 0
+                && validations.iter().all(|validation|
+                {
+                    matches!
+                    (
+                        validation.additional_properties.as_deref(),
+                        None | Some(Schema::Bool(false)),
+                    )
+                });
+            if is_closed == false
+            {
+                return;
+            }
+            for (name, value) in map
+            {
+                let field = if path.len() == 0
+                {
+                    name.clone()
+                }
+                else
+                {
+                    path.to_string() + "." + name
+                };
+                let property = validations
+                    .iter()
+                    .find_map(|validation| validation.properties.get(name));
+                match property
+                {
+                    Some(property) =>
+                    {
+                        find_unknown_fields
+                        (
+                            root,
+                            property,
+                            value,
+                            &field,
+                            unknown_fields,
+                        );
+                    },
+                    None => unknown_fields.push(field),
+                }
+            }
+        },
+        Value::Array(values) =>
+        {
+            let items = objects
+                .iter()
+                .filter_map(|object| object.array.as_deref())
+                .find_map(|array| match &array.items
+                {
+                    Some(SingleOrVec::Single(items)) => Some(items),
+                    _ => None,
+                });
+            if let Some(items) = items
+            {
+                for (i, value) in values.iter().enumerate()
+                {
+                    let field = format!("{}[{}]", path, i);
+                    find_unknown_fields
+                    (
+                        root,
+                        items,
+                        value,
+                        &field,
+                        unknown_fields,
+                    );
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Collects the schema objects that the schema consists of, following the
+/// references and the subschemas.
+//------------------------------------------------------------------------------
+fn resolve_schema<'a>
+(
+    root: &'a RootSchema,
+    schema: &'a Schema,
+    objects: &mut Vec<&'a SchemaObject>,
+)
+{
+    let object = match schema
+    {
+        Schema::Object(object) => object,
+        Schema::Bool(_) => return,
+    };
+    if let Some(reference) = &object.reference
+    {
+        let name = reference.trim_start_matches("#/definitions/");
+        if let Some(definition) = root.definitions.get(name)
+        {
+            resolve_schema(root, definition, objects);
+        }
+    }
+    if let Some(subschemas) = &object.subschemas
+    {
+        let lists =
+        [
+            &subschemas.all_of,
+            &subschemas.any_of,
+            &subschemas.one_of,
+        ];
+        for schema in lists.into_iter().flatten().flatten()
+        {
+            resolve_schema(root, schema, objects);
+        }
+    }
+    objects.push(object);
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the identity file is an absolute path to an existing file.
+/// `~/` is accepted as the home directory.
+//------------------------------------------------------------------------------
+fn check_identity_file
+(
+    diagnostics: &mut Diagnostics,
+    key: &str,
+    config: &SshConfig,
+    path: &str,
+    connect_info: &ConnectInfo,
+)
+{
+    let identity_file = connect_info.identity_file();
+    if identity_file.len() == 0
+    {
+        return;
+    }
+
+    let field = path.to_string() + ".identity_file";
+    let file = match identity_file.strip_prefix("~/")
+    {
+        Some(rest) =>
+        {
+            std::env::var("HOME").unwrap_or("".to_string()) + "/" + rest
+        },
+        None => identity_file.clone(),
+    };
+    let message = if file.starts_with('/') == false
+    {
+        "must be an absolute path".to_string()
+    }
+    else if Path::new(&file).is_file() == false
+    {
+        format!("{} does not exist", identity_file)
+    }
+    else
+    {
+        return;
+    };
+    add(diagnostics, key, config, &field, &message);
+}
+
+//------------------------------------------------------------------------------
+/// Returns the fields that the subcommand requires.
+///
+/// # Arguments
+///
+/// - `command` - Name of the subcommand, such as `backup-db`.
+//------------------------------------------------------------------------------
+pub fn get_required_fields( command: &str ) -> &'static [&'static str]
+{
+    match command
+    {
+        "replace" | "patch" => &["remote_path", "git_path", "git_src_path"],
+        "rollback" | "status" | "unlock" => &["remote_path"],
+        "diff" | "pull" => &["remote_path", "git_src_path"],
+        "clear" => &["remote_cache_path"],
+        "backup" => &["remote_path", "backup_path"],
+        "backup-db" => &["db_name", "backup_path"],
+        _ => &[],
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Checks that the entries set the fields that the subcommand requires.
+///
+/// # Arguments
+///
+/// - `diagnostics` - Diagnostics to add the problems to.
+/// - `config_entries` - Configuration entries keyed by `project_environment`.
+/// - `command` - Name of the subcommand, such as `backup-db`.
+//------------------------------------------------------------------------------
+pub fn check_required_fields
+(
+    diagnostics: &mut Diagnostics,
+    config_entries: &BTreeMap<String, SshConfig>,
+    command: &str,
+)
+{
+    for (key, config) in config_entries
+    {
+        let value = serde_json::to_value(config).unwrap();
+        for field in get_required_fields(command)
+        {
+            let is_set = match &value[field]
+            {
+                Value::Null => false,
+                Value::String(s) => s.len() > 0,
+                _ => true,
+            };
+            if is_set == false
+            {
+                let message = format!("is required by {}", command);
+                add(diagnostics, key, config, field, &message);
+            }
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+/// Adds a diagnostic of the field of the entry, located in the entry that the
+/// field is written in.
+//------------------------------------------------------------------------------
+fn add
+(
+    diagnostics: &mut Diagnostics,
+    key: &str,
+    config: &SshConfig,
+    field: &str,
+    message: &str,
+)
+{
+    let origin = find_origin(config.origins(), field).or(config.location());
+    if let Some(origin) = origin
+    {
+        diagnostics.add(key, origin, field, message);
+    }
+}